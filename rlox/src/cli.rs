@@ -3,31 +3,36 @@ use std::fs::read_to_string;
 
 #[derive(Debug)]
 pub struct CliArgs {
+    // "rlox --check script.lox" type checks the script instead of running it.
+    pub check: bool,
     pub source: String,
+    // everything after the script path, handed to the script via "args()".
+    pub script_args: Vec<String>,
 }
 
 #[derive(Debug)]
 pub enum CliError {
-    InvalidArgumentsLength,
     NoArguments,
     FileReadError { path: String, error: std::io::Error },
 }
 
 impl CliArgs {
     pub fn new() -> Result<CliArgs, CliError> {
-        let mut args: Vec<String> = env::args().skip(1).collect();
-
-        if args.len() == 0 {
-            return Err(CliError::NoArguments);
-        }
-
-        if args.len() > 1 {
-            return Err(CliError::InvalidArgumentsLength);
-        }
-
-        let source = args.pop().unwrap();
+        Self::from_args(env::args().skip(1))
+    }
 
-        Ok(CliArgs { source })
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<CliArgs, CliError> {
+        let mut args = args.peekable();
+        let check = args.next_if_eq("--check").is_some();
+        // "--" ends the options, for a script whose name starts with "--".
+        args.next_if_eq("--");
+        let source = args.next().ok_or(CliError::NoArguments)?;
+        let script_args = args.collect();
+        Ok(CliArgs {
+            check,
+            source,
+            script_args,
+        })
     }
 }
 
@@ -47,3 +52,34 @@ impl Cli {
         Ok(Cli { args, source })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> CliArgs {
+        CliArgs::from_args(args.iter().map(|a| a.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_options() {
+        let args = parse(&["--check", "script.lox"]);
+        assert!(args.check);
+        assert_eq!(args.source, "script.lox");
+
+        // a script can be named "check", and its arguments aren't options.
+        let args = parse(&["check", "--check"]);
+        assert!(!args.check);
+        assert_eq!(args.source, "check");
+        assert_eq!(args.script_args, vec!["--check"]);
+
+        let args = parse(&["--", "--check"]);
+        assert!(!args.check);
+        assert_eq!(args.source, "--check");
+
+        assert!(matches!(
+            CliArgs::from_args(["--check".to_string()].into_iter()),
+            Err(CliError::NoArguments)
+        ));
+    }
+}
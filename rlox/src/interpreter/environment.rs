@@ -1,19 +1,70 @@
 use crate::interpreter::primitive::LoxObject;
+use crate::language::symbol::{Symbol, SymbolMap, SymbolSet};
+use crate::language::token::{Slot, Token};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssignError {
+    Undefined,
+    Const,
+}
+
+// where a variable's value lives, shared with every closure that captured it.
+pub type Cell = Rc<RefCell<LoxObject>>;
+
+// a local variable is kept inline until a closure captures it, only then it
+// moves into a cell the two share. Calls that create no closures don't pay
+// for cells.
+#[derive(Debug, Clone)]
+enum Local {
+    Value(LoxObject),
+    Captured(Cell),
+}
+
+impl Local {
+    fn get(&self) -> LoxObject {
+        match self {
+            Local::Value(v) => v.clone(),
+            Local::Captured(cell) => cell.borrow().clone(),
+        }
+    }
+
+    fn set(&mut self, v: LoxObject) {
+        match self {
+            Local::Value(value) => *value = v,
+            Local::Captured(cell) => *cell.borrow_mut() = v,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Environment {
-    values: HashMap<String, LoxObject>,
+    // local variables, at the index the resolver gave them (see Slot). A
+    // slot stays empty until its declaration runs.
+    slots: Vec<Option<Local>>,
+    // the names defined at the top level of a script or module, and the
+    // natives, which are looked up by name as the program runs.
+    globals: SymbolMap<Cell>,
+    consts: SymbolSet,
     parent: Option<Rc<RefCell<Environment>>>,
+    // the top level of a script or module, see capture.
+    is_module: bool,
+    // the file an imported module was loaded from.
+    path: Option<PathBuf>,
 }
 
 impl Environment {
     pub fn new(parent: Option<Rc<RefCell<Environment>>>) -> Self {
         Self {
-            values: HashMap::new(),
+            slots: Vec::new(),
+            globals: SymbolMap::default(),
+            consts: SymbolSet::default(),
             parent,
+            is_module: false,
+            path: None,
         }
     }
 
@@ -21,42 +72,283 @@ impl Environment {
         Rc::new(RefCell::new(Self::new(parent)))
     }
 
-    pub fn deep_clone(&self) -> Self {
-        Self {
-            values: self.values.clone(),
-            parent: self.parent.clone(),
+    pub fn new_module(parent: Option<Rc<RefCell<Environment>>>) -> Rc<RefCell<Self>> {
+        let mut env = Self::new(parent);
+        env.is_module = true;
+        Rc::new(RefCell::new(env))
+    }
+
+    pub fn new_module_at(
+        parent: Option<Rc<RefCell<Environment>>>,
+        path: &Path,
+    ) -> Rc<RefCell<Self>> {
+        let module = Self::new_module(parent);
+        module.borrow_mut().path = Some(path.to_path_buf());
+        module
+    }
+
+    // the file of the module the code running in env was written in, functions
+    // keep their module as a parent so this holds wherever they are called.
+    pub fn module_path(env: &Rc<RefCell<Environment>>) -> Option<PathBuf> {
+        let env = env.borrow();
+        match &env.parent {
+            _ if env.is_module => env.path.clone(),
+            Some(parent) => Self::module_path(parent),
+            None => None,
         }
     }
 
-    pub fn define(&mut self, k: String, v: LoxObject) {
-        self.values.insert(k, v);
+    // the environment a function closes over. The cells of the variables it
+    // captured (as listed by the resolver) fill its slots, while the script
+    // or module scope it was created in is its parent since names there are
+    // looked up when called (they may be defined later on). Capturing a local
+    // that isn't defined yet (a function declared further down the block)
+    // reserves its cell, which the declaration fills in.
+    pub fn capture(env: &Rc<RefCell<Environment>>, captures: &[Slot]) -> Rc<RefCell<Environment>> {
+        let mut module = env.clone();
+        loop {
+            let parent = match &module.borrow().parent {
+                Some(parent) if !module.borrow().is_module => parent.clone(),
+                _ => break,
+            };
+            module = parent;
+        }
+        if captures.is_empty() {
+            return module;
+        }
+
+        let mut closure = Environment::new(Some(module));
+        closure.slots = captures
+            .iter()
+            .map(|slot| {
+                let cell = env.borrow_mut().local_mut(*slot, |local| {
+                    let value = match local.take() {
+                        Some(Local::Captured(cell)) => return cell,
+                        Some(Local::Value(value)) => value,
+                        None => LoxObject::Nil,
+                    };
+                    let cell = Rc::new(RefCell::new(value));
+                    *local = Some(Local::Captured(cell.clone()));
+                    cell
+                });
+                cell.map(Local::Captured)
+            })
+            .collect();
+        Rc::new(RefCell::new(closure))
     }
 
-    pub fn get(&mut self, k: &str) -> Option<LoxObject> {
-        if let Some(v) = self.values.get(k) {
-            return Some(v.clone());
+    fn local<R>(&self, slot: Slot, f: impl FnOnce(Option<&Local>) -> R) -> R {
+        if slot.depth == 0 {
+            return f(self.slots.get(slot.index as usize).and_then(Option::as_ref));
         }
+        match &self.parent {
+            Some(parent) => parent.borrow().local(
+                Slot {
+                    depth: slot.depth - 1,
+                    ..slot
+                },
+                f,
+            ),
+            None => f(None),
+        }
+    }
 
-        if let Some(ref p) = self.parent {
-            p.borrow_mut().get(k)
-        } else {
-            None
+    fn local_mut<R>(&mut self, slot: Slot, f: impl FnOnce(&mut Option<Local>) -> R) -> Option<R> {
+        if slot.depth == 0 {
+            let index = slot.index as usize;
+            if index >= self.slots.len() {
+                self.slots.resize(index + 1, None);
+            }
+            return Some(f(&mut self.slots[index]));
         }
+        self.parent.as_ref()?.borrow_mut().local_mut(
+            Slot {
+                depth: slot.depth - 1,
+                ..slot
+            },
+            f,
+        )
+    }
+
+    fn global_cell(&self, symbol: Symbol) -> Option<Cell> {
+        if let Some(cell) = self.globals.get(&symbol) {
+            return Some(cell.clone());
+        }
+        self.parent.as_ref()?.borrow().global_cell(symbol)
+    }
+
+    // detaches every local variable in this scope from the closures created
+    // until now, which keep the old cells and with them the values they held.
+    pub fn refresh(&mut self) {
+        for local in self.slots.iter_mut().flatten() {
+            if let Local::Captured(cell) = local {
+                let value = cell.borrow().clone();
+                *local = Local::Value(value);
+            }
+        }
+    }
+
+    // redefining a name in the same scope reuses its cell, so closures that
+    // captured it see the new value.
+    pub fn define(&mut self, name: &Token, v: LoxObject) {
+        let Some(slot) = name.slot else {
+            self.define_global(name.symbol(), v);
+            return;
+        };
+        self.local_mut(slot, |local| match local {
+            Some(local) => local.set(v),
+            None => *local = Some(Local::Value(v)),
+        });
     }
 
-    pub fn assign(&mut self, k: String, v: LoxObject) -> Result<(), ()> {
-        if self.values.contains_key(&k) {
-            self.values.get_mut(&k).map(|x| *x = v);
+    pub fn define_global(&mut self, name: impl Into<Symbol>, v: LoxObject) {
+        match self.globals.entry(name.into()) {
+            Entry::Occupied(entry) => *entry.get().borrow_mut() = v,
+            Entry::Vacant(entry) => {
+                entry.insert(Rc::new(RefCell::new(v)));
+            }
+        }
+    }
+
+    // like define, but refuses to overwrite a const declared in this scope,
+    // used for the declarations written in scripts (var, fun, import...).
+    // The resolver already rejects this for local variables.
+    pub fn declare(&mut self, name: &Token, v: LoxObject) -> Result<(), AssignError> {
+        if name.slot.is_none() && self.consts.contains(&name.symbol()) {
+            return Err(AssignError::Const);
+        }
+        self.define(name, v);
+        Ok(())
+    }
+
+    pub fn declare_const(&mut self, name: &Token, v: LoxObject) -> Result<(), AssignError> {
+        self.declare(name, v)?;
+        if name.slot.is_none() {
+            self.consts.insert(name.symbol());
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &Token) -> Option<LoxObject> {
+        match name.slot {
+            Some(slot) => self.local(slot, |local| local.map(Local::get)),
+            None => self.global(name.symbol()),
+        }
+    }
+
+    pub fn global(&self, name: impl Into<Symbol>) -> Option<LoxObject> {
+        self.global_cell(name.into())
+            .map(|cell| cell.borrow().clone())
+    }
+
+    pub fn assign(&mut self, name: &Token, v: LoxObject) -> Result<(), AssignError> {
+        match name.slot {
+            Some(slot) => self
+                .local_mut(slot, |local| match local {
+                    Some(local) => {
+                        local.set(v);
+                        Ok(())
+                    }
+                    None => Err(AssignError::Undefined),
+                })
+                .unwrap_or(Err(AssignError::Undefined)),
+            None => self.assign_global(name.symbol(), v),
+        }
+    }
+
+    fn assign_global(&mut self, symbol: Symbol, v: LoxObject) -> Result<(), AssignError> {
+        if let Some(cell) = self.globals.get(&symbol) {
+            if self.consts.contains(&symbol) {
+                return Err(AssignError::Const);
+            }
+            *cell.borrow_mut() = v;
             Ok(())
         } else if let Some(ref p) = self.parent {
-            p.borrow_mut().assign(k, v)
+            p.borrow_mut().assign_global(symbol, v)
         } else {
-            Err(())
+            Err(AssignError::Undefined)
         }
     }
 
+    // a name defined directly in this scope, like bindings.
+    pub fn binding(&self, name: &str) -> Option<LoxObject> {
+        self.globals
+            .get(&Symbol::from(name))
+            .map(|cell| cell.borrow().clone())
+    }
+
+    // the names defined directly in this scope (ignoring parents and locals).
+    pub fn bindings(&self) -> impl Iterator<Item = (Symbol, LoxObject)> + '_ {
+        self.globals
+            .iter()
+            .map(|(k, cell)| (*k, cell.borrow().clone()))
+    }
+
     pub fn print_map(&self) {
-        let msg: String = self.values.iter().map(|(k, v)| format!("{k}{v}")).collect();
+        let msg: String = self.bindings().map(|(k, v)| format!("{k}{v}")).collect();
         println!("msg {}", msg);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::language::token::{Coordinate, Literal, TokenType};
+
+    fn name(lexeme: &str, slot: Option<(u32, u32)>) -> Token {
+        let mut token = Token::new(
+            TokenType::Identifier,
+            Some(lexeme.into()),
+            Literal::Nil,
+            Coordinate::default(),
+        );
+        token.slot = slot.map(|(depth, index)| Slot { depth, index });
+        token
+    }
+
+    #[test]
+    fn test_capture_only_keeps_referenced_cells() {
+        let module = Environment::new_module(None);
+        module
+            .borrow_mut()
+            .define_global("global", LoxObject::Integer(0));
+        let outer = Environment::new_rc(Some(module.clone()));
+        outer
+            .borrow_mut()
+            .define(&name("used", Some((0, 0))), LoxObject::Integer(1));
+        outer
+            .borrow_mut()
+            .define(&name("unused", Some((0, 1))), LoxObject::Integer(2));
+        let inner = Environment::new_rc(Some(outer.clone()));
+        inner
+            .borrow_mut()
+            .define(&name("used", Some((0, 0))), LoxObject::Integer(3));
+
+        let captures = [Slot { depth: 0, index: 0 }];
+        let closure = Environment::capture(&inner, &captures);
+        assert_eq!(Rc::strong_count(&outer), 2);
+        let used = name("used", Some((0, 0)));
+        assert_eq!(closure.borrow().get(&used), Some(LoxObject::Integer(3)));
+        assert_eq!(closure.borrow().get(&name("unused", Some((0, 1)))), None);
+        assert_eq!(
+            closure.borrow().get(&name("global", None)),
+            Some(LoxObject::Integer(0))
+        );
+
+        // the cell is shared both ways, until the scope is refreshed.
+        inner
+            .borrow_mut()
+            .assign(&used, LoxObject::Integer(4))
+            .unwrap();
+        assert_eq!(closure.borrow().get(&used), Some(LoxObject::Integer(4)));
+        inner.borrow_mut().refresh();
+        inner
+            .borrow_mut()
+            .assign(&used, LoxObject::Integer(5))
+            .unwrap();
+        assert_eq!(closure.borrow().get(&used), Some(LoxObject::Integer(4)));
+
+        // a function that captures nothing closes over the module itself.
+        assert!(Rc::ptr_eq(&Environment::capture(&inner, &[]), &module));
+    }
+}
@@ -1,78 +1,318 @@
 use crate::interpreter::primitive::LoxObject;
-use crate::language::token::Token;
+use crate::language::token::{Coordinate, Token};
 use std::fmt;
 use thiserror::Error;
 
 #[derive(Debug, Error, PartialEq, Clone)]
 pub enum RuntimeError {
     InvalidMathOp(String, Token, String),
+    // integer overflow or division by zero, with the reason as the last field.
+    ArithmeticError(String, Token, String, &'static str),
     InvalidComparisonOp(String, Token, String),
     InvalidUnaryOp(Token, String),
     InvalidLogicalOp(Token),
     UndefinedVariable(Token),
+    ConstReassignment(Token),
     Uncallable(LoxObject, Token),
+    ArityMismatch(Token, usize, Option<usize>, usize),
+    InvalidIndex(LoxObject, Token, LoxObject),
+    InvalidProperty(LoxObject, Token),
+    IndexOutOfRange(Token, f64, usize),
+    InvalidArgument(&'static str, usize, &'static str, LoxObject),
+    CapabilityDisabled(&'static str, &'static str),
+    Exit(i32),
+    ImportFailed(Token, String, String),
+    // no arm of a match expression accepted the value.
+    NoMatch(LoxObject, Token),
+    // the value doesn't have the shape a destructuring pattern asks for.
+    InvalidDestructure(LoxObject, Token),
+    // every fiber left is waiting on a channel nothing can send to anymore.
+    Deadlock(usize),
+    // for-in over something that isn't a list, map, string or iterator.
+    NotIterable(LoxObject, Token),
     Native(String),
+    // a value raised by a "throw" statement.
+    Thrown(LoxObject, Token),
+    // an error raised inside of a native, located at the call site.
+    AtCall(Box<RuntimeError>, Token),
+}
+
+impl RuntimeError {
+    // exiting unwinds the whole interpreter, everything else can be caught.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(self, RuntimeError::Exit(_))
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RuntimeError::InvalidMathOp(..)
+            | RuntimeError::InvalidComparisonOp(..)
+            | RuntimeError::InvalidUnaryOp(..)
+            | RuntimeError::InvalidLogicalOp(..)
+            | RuntimeError::Uncallable(..)
+            | RuntimeError::InvalidIndex(..)
+            | RuntimeError::InvalidProperty(..)
+            | RuntimeError::InvalidArgument(..)
+            | RuntimeError::InvalidDestructure(..)
+            | RuntimeError::NotIterable(..)
+            | RuntimeError::ConstReassignment(..) => "TypeError",
+            RuntimeError::UndefinedVariable(..) => "NameError",
+            RuntimeError::ArithmeticError(..) => "ArithmeticError",
+            RuntimeError::ArityMismatch(..) => "ArityError",
+            RuntimeError::IndexOutOfRange(..) => "IndexError",
+            RuntimeError::CapabilityDisabled(..) => "CapabilityError",
+            RuntimeError::Exit(..) => "Exit",
+            RuntimeError::ImportFailed(..) => "ImportError",
+            RuntimeError::NoMatch(..) => "MatchError",
+            RuntimeError::Deadlock(..) => "DeadlockError",
+            RuntimeError::Native(..) => "Error",
+            RuntimeError::Thrown(..) => "Thrown",
+            RuntimeError::AtCall(inner, _) => inner.kind(),
+        }
+    }
+
+    pub fn coordinate(&self) -> Option<&Coordinate> {
+        match self {
+            RuntimeError::InvalidMathOp(_, tok, _)
+            | RuntimeError::ArithmeticError(_, tok, ..)
+            | RuntimeError::InvalidComparisonOp(_, tok, _)
+            | RuntimeError::InvalidUnaryOp(tok, _)
+            | RuntimeError::InvalidLogicalOp(tok)
+            | RuntimeError::UndefinedVariable(tok)
+            | RuntimeError::ConstReassignment(tok)
+            | RuntimeError::Uncallable(_, tok)
+            | RuntimeError::ArityMismatch(tok, ..)
+            | RuntimeError::InvalidIndex(_, tok, _)
+            | RuntimeError::InvalidProperty(_, tok)
+            | RuntimeError::IndexOutOfRange(tok, ..)
+            | RuntimeError::ImportFailed(tok, ..)
+            | RuntimeError::NoMatch(_, tok)
+            | RuntimeError::InvalidDestructure(_, tok)
+            | RuntimeError::NotIterable(_, tok)
+            | RuntimeError::Thrown(_, tok)
+            | RuntimeError::AtCall(_, tok) => Some(&tok.coordinate),
+            RuntimeError::InvalidArgument(..)
+            | RuntimeError::Deadlock(..)
+            | RuntimeError::CapabilityDisabled(..)
+            | RuntimeError::Exit(..)
+            | RuntimeError::Native(..) => None,
+        }
+    }
+
+    // the error text without the "RuntimeError: " prefix or the coordinate,
+    // which a caught error carries separately as its line.
+    pub fn message(&self) -> String {
+        struct Message<'a>(&'a RuntimeError);
+
+        impl fmt::Display for Message<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.write_message(f, false)
+            }
+        }
+
+        Message(self).to_string()
+    }
 }
 
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "RuntimeError: ")?;
         match self {
+            RuntimeError::Thrown(..) => write!(f, "Uncaught exception: ")?,
+            _ => write!(f, "RuntimeError: ")?,
+        }
+        self.write_message(f, true)
+    }
+}
+
+impl RuntimeError {
+    fn write_message(&self, f: &mut fmt::Formatter, located: bool) -> fmt::Result {
+        let at = |coordinate: &Coordinate| match located {
+            true => format!(" {}", coordinate),
+            false => String::new(),
+        };
+        match self {
+            RuntimeError::ArithmeticError(left, op, right, reason) => {
+                write!(
+                    f,
+                    "Arithmetic error in \"{} {} {}\", {}{}",
+                    left,
+                    op.with_lexeme(|lex| lex.to_string()),
+                    right,
+                    reason,
+                    at(&op.coordinate)
+                )
+            }
             RuntimeError::InvalidMathOp(left, op, right) => {
                 write!(
                     f,
-                    "Invalid math operation \"{} {} {}\" {}",
+                    "Invalid math operation \"{} {} {}\"{}",
                     left,
                     op.with_lexeme(|lex| lex.to_string()),
                     right,
-                    op.coordinate
+                    at(&op.coordinate)
                 )
             }
             RuntimeError::InvalidComparisonOp(left, op, right) => {
                 write!(
                     f,
-                    "Invalid comparison operation \"{} {} {}\" {}",
+                    "Invalid comparison operation \"{} {} {}\"{}",
                     left,
                     op.with_lexeme(|lex| lex.to_string()),
                     right,
-                    op.coordinate
+                    at(&op.coordinate)
                 )
             }
             RuntimeError::InvalidUnaryOp(op, right) => {
                 write!(
                     f,
-                    "Invalid unary operation \"{} {}\" {}",
+                    "Invalid unary operation \"{} {}\"{}",
                     op.with_lexeme(|lex| lex.to_string()),
                     right,
-                    op.coordinate
+                    at(&op.coordinate)
                 )
             }
             RuntimeError::UndefinedVariable(name) => {
                 write!(
                     f,
-                    "Undefined variable \"{}\" {}",
+                    "Undefined variable \"{}\"{}",
                     name.with_lexeme(|lex| lex.to_string()),
-                    name.coordinate
+                    at(&name.coordinate)
+                )
+            }
+            RuntimeError::ConstReassignment(name) => {
+                write!(
+                    f,
+                    "Cannot reassign const \"{}\"{}",
+                    name.with_lexeme(|lex| lex.to_string()),
+                    at(&name.coordinate)
                 )
             }
             RuntimeError::InvalidLogicalOp(op) => {
                 write!(
                     f,
-                    "Invalid logical operation \"{}\" {}",
+                    "Invalid logical operation \"{}\"{}",
                     op.with_lexeme(|lex| lex.to_string()),
-                    op.coordinate
+                    at(&op.coordinate)
                 )
             }
             RuntimeError::Uncallable(obj, tok) => {
                 write!(
                     f,
-                    "Invalid call expression trying to call literal value -> {} {}",
-                    obj, tok.coordinate
+                    "Invalid call expression trying to call literal value -> {}{}",
+                    obj,
+                    at(&tok.coordinate)
+                )
+            }
+            RuntimeError::InvalidIndex(obj, tok, index) => {
+                write!(
+                    f,
+                    "Invalid index expression \"{}[{}]\"{}",
+                    obj,
+                    index,
+                    at(&tok.coordinate)
+                )
+            }
+            RuntimeError::InvalidProperty(obj, name) => {
+                write!(
+                    f,
+                    "Invalid property access \"{}\" on {} value \"{}\"{}",
+                    name.lexeme_or_empty(),
+                    obj.type_name(),
+                    obj,
+                    at(&name.coordinate)
+                )
+            }
+            RuntimeError::IndexOutOfRange(tok, index, len) => {
+                write!(
+                    f,
+                    "Index {} out of range for length {}{}",
+                    index,
+                    len,
+                    at(&tok.coordinate)
+                )
+            }
+            RuntimeError::InvalidArgument(func, position, expected, found) => {
+                write!(
+                    f,
+                    "Invalid argument {} to \"{}\", expected {} but got {} \"{}\"",
+                    position,
+                    func,
+                    expected,
+                    found.type_name(),
+                    found
+                )
+            }
+            RuntimeError::CapabilityDisabled(func, capability) => {
+                write!(
+                    f,
+                    "\"{}\" requires the \"{}\" capability which is disabled",
+                    func, capability
+                )
+            }
+            RuntimeError::Exit(code) => {
+                write!(f, "Script exited with code {}", code)
+            }
+            RuntimeError::ArityMismatch(paren, min, max, got) => {
+                match max {
+                    Some(max) if max == min => write!(f, "Expected {} arguments", min)?,
+                    Some(max) => write!(f, "Expected {} to {} arguments", min, max)?,
+                    None => write!(f, "Expected at least {} arguments", min)?,
+                }
+                write!(f, " but got {}{}", got, at(&paren.coordinate))
+            }
+            RuntimeError::ImportFailed(keyword, path, reason) => {
+                write!(
+                    f,
+                    "Failed to import \"{}\"{}\n{}",
+                    path,
+                    at(&keyword.coordinate),
+                    reason
+                )
+            }
+            RuntimeError::NoMatch(value, keyword) => {
+                write!(
+                    f,
+                    "No match arm for {} value \"{}\"{}",
+                    value.type_name(),
+                    value,
+                    at(&keyword.coordinate)
+                )
+            }
+            RuntimeError::InvalidDestructure(value, tok) => {
+                write!(
+                    f,
+                    "Cannot destructure {} value \"{}\"{}",
+                    value.type_name(),
+                    value,
+                    at(&tok.coordinate)
+                )
+            }
+            RuntimeError::Deadlock(blocked) => {
+                write!(
+                    f,
+                    "Deadlock, {} fibers are waiting on channels nothing will send to",
+                    blocked
+                )
+            }
+            RuntimeError::NotIterable(value, tok) => {
+                write!(
+                    f,
+                    "Cannot iterate over {} value \"{}\"{}",
+                    value.type_name(),
+                    value,
+                    at(&tok.coordinate)
                 )
             }
             RuntimeError::Native(s) => {
                 write!(f, "{}", s)
             }
+            RuntimeError::Thrown(value, keyword) => {
+                write!(f, "{}{}", value, at(&keyword.coordinate))
+            }
+            RuntimeError::AtCall(inner, paren) => {
+                inner.write_message(f, located)?;
+                write!(f, "{}", at(&paren.coordinate))
+            }
         }
     }
 }
@@ -0,0 +1,455 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::{Callable, LoxObject};
+use crate::interpreter::stdlib::fiber;
+use crate::interpreter::visitor::{is_truthy, LoxVisitor};
+use crate::language::ast::{Expr, Stmt};
+use crate::language::token::Token;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+// what "for-in" walks over. Lists, map keys and the characters of a string
+// are snapshotted up front, while a map with a "next" function (generators
+// included) is asked for one value at a time and answers with
+// {value: v, done: false} until it returns {done: true}.
+#[derive(Debug)]
+pub enum LoxIterator {
+    Values(std::vec::IntoIter<LoxObject>),
+    Protocol(Rc<dyn Callable>),
+}
+
+impl LoxIterator {
+    pub fn new(iterable: LoxObject, tok: &Token) -> Result<Self, RuntimeError> {
+        let values: Vec<LoxObject> = match &iterable {
+            LoxObject::List(values) => values.borrow().clone(),
+            LoxObject::String(s) => s.chars().map(|c| LoxObject::String(c.into())).collect(),
+            LoxObject::Map(entries) => match entries.borrow().get("next") {
+                Some(LoxObject::Function(next)) => return Ok(LoxIterator::Protocol(next.clone())),
+                _ => entries
+                    .borrow()
+                    .keys()
+                    .map(|k| LoxObject::String(k.clone()))
+                    .collect(),
+            },
+            _ => return Err(RuntimeError::NotIterable(iterable, tok.clone())),
+        };
+        Ok(LoxIterator::Values(values.into_iter()))
+    }
+
+    pub fn next(
+        &mut self,
+        interpreter: &mut LoxVisitor,
+        tok: &Token,
+    ) -> Result<Option<LoxObject>, RuntimeError> {
+        let next = match self {
+            LoxIterator::Values(values) => return Ok(values.next()),
+            LoxIterator::Protocol(next) => next.clone(),
+        };
+
+        let result = next.call(interpreter, &[])?;
+        let LoxObject::Map(entries) = &result else {
+            return Err(RuntimeError::NotIterable(result, tok.clone()));
+        };
+        let entries = entries.borrow();
+        if entries.get("done").is_some_and(is_truthy) {
+            return Ok(None);
+        }
+        Ok(Some(
+            entries.get("value").cloned().unwrap_or(LoxObject::Nil),
+        ))
+    }
+}
+
+// where a suspended generator picks up again, innermost last. Frames hold
+// their own copies of the statements they unroll, made once as the statement
+// is entered (loops share theirs between iterations).
+#[derive(Debug)]
+enum Frame {
+    Block {
+        statements: Rc<[Stmt]>,
+        pc: usize,
+        env: Rc<RefCell<Environment>>,
+    },
+    While {
+        condition: Expr,
+        body: Rc<[Stmt]>,
+        env: Rc<RefCell<Environment>>,
+    },
+    // "started" once the first iteration ran, every later one begins by
+    // refreshing the loop variables and running the increment.
+    For {
+        condition: Expr,
+        increment: Option<Expr>,
+        body: Rc<[Stmt]>,
+        env: Rc<RefCell<Environment>>,
+        started: bool,
+    },
+    ForIn {
+        iterator: LoxIterator,
+        keyword: Token,
+        name: Token,
+        body: Rc<[Stmt]>,
+        env: Rc<RefCell<Environment>>,
+    },
+    // "var name = yield ...;" waiting for the value it's resumed with.
+    Receive {
+        name: Token,
+        env: Rc<RefCell<Environment>>,
+    },
+}
+
+enum Step {
+    Yielded(LoxObject),
+    Returned(LoxObject),
+}
+
+// the body of a generator function mid execution. The visitor can't suspend
+// half way through a statement, so statements containing a yield (blocks,
+// ifs and loops) are unrolled into frames here while everything else is
+// handed to the visitor whole.
+#[derive(Debug)]
+pub struct Generator {
+    frames: RefCell<Vec<Frame>>,
+}
+
+impl Generator {
+    // the object returned by calling a generator function, {next: fun}.
+    pub fn object(body: Rc<[Stmt]>, env: Rc<RefCell<Environment>>) -> LoxObject {
+        let generator = Generator {
+            frames: RefCell::new(vec![Frame::Block {
+                statements: body,
+                pc: 0,
+                env,
+            }]),
+        };
+        let next = LoxObject::Function(Rc::new(GeneratorNext(Rc::new(generator))));
+        LoxObject::map(BTreeMap::from([("next".to_string(), next)]))
+    }
+
+    fn resume(&self, interpreter: &mut LoxVisitor, sent: LoxObject) -> Result<Step, RuntimeError> {
+        let mut frames = self
+            .frames
+            .try_borrow_mut()
+            .map_err(|_| RuntimeError::Native("generator is already running".to_string()))?;
+
+        let step = run(&mut frames, interpreter, sent);
+        // returning or throwing finishes the generator for good.
+        if !matches!(step, Ok(Step::Yielded(_))) {
+            frames.clear();
+        }
+        step
+    }
+}
+
+fn run(
+    frames: &mut Vec<Frame>,
+    interpreter: &mut LoxVisitor,
+    sent: LoxObject,
+) -> Result<Step, RuntimeError> {
+    let mut sent = Some(sent);
+
+    while let Some(frame) = frames.last_mut() {
+        match frame {
+            // only ever on top when resuming, so this takes what was sent.
+            Frame::Receive { name, env } => {
+                let value = sent.take().unwrap_or(LoxObject::Nil);
+                env.borrow_mut().define(name, value);
+                frames.pop();
+            }
+            Frame::Block {
+                statements,
+                pc,
+                env,
+            } => {
+                if *pc >= statements.len() {
+                    frames.pop();
+                    continue;
+                }
+                let (statements, env) = (statements.clone(), env.clone());
+                let stmt = &statements[*pc];
+                *pc += 1;
+                if let Some(step) = execute(frames, interpreter, stmt, env)? {
+                    return Ok(step);
+                }
+            }
+            Frame::While {
+                condition,
+                body,
+                env,
+            } => {
+                if is_truthy(&interpreter.evaluate_in(env.clone(), condition)?) {
+                    let iteration = Frame::Block {
+                        statements: body.clone(),
+                        pc: 0,
+                        env: env.clone(),
+                    };
+                    frames.push(iteration);
+                } else {
+                    frames.pop();
+                }
+            }
+            Frame::For {
+                condition,
+                increment,
+                body,
+                env,
+                started,
+            } => {
+                if *started {
+                    env.borrow_mut().refresh();
+                    if let Some(increment) = increment {
+                        interpreter.evaluate_in(env.clone(), increment)?;
+                    }
+                }
+                *started = true;
+                if is_truthy(&interpreter.evaluate_in(env.clone(), condition)?) {
+                    let iteration = Frame::Block {
+                        statements: body.clone(),
+                        pc: 0,
+                        env: env.clone(),
+                    };
+                    frames.push(iteration);
+                } else {
+                    frames.pop();
+                }
+            }
+            Frame::ForIn {
+                iterator,
+                keyword,
+                name,
+                body,
+                env,
+            } => match iterator.next(interpreter, keyword)? {
+                Some(value) => {
+                    let scope = Environment::new_rc(Some(env.clone()));
+                    scope.borrow_mut().define(name, value);
+                    let iteration = Frame::Block {
+                        statements: body.clone(),
+                        pc: 0,
+                        env: scope,
+                    };
+                    frames.push(iteration);
+                }
+                None => {
+                    frames.pop();
+                }
+            },
+        }
+    }
+
+    Ok(Step::Returned(LoxObject::Nil))
+}
+
+fn execute(
+    frames: &mut Vec<Frame>,
+    interpreter: &mut LoxVisitor,
+    stmt: &Stmt,
+    env: Rc<RefCell<Environment>>,
+) -> Result<Option<Step>, RuntimeError> {
+    if !stmt.contains_yield() {
+        let result = interpreter.execute_block(env, std::slice::from_ref(stmt))?;
+        fiber::expect_yielded(interpreter, None)?;
+        return match result {
+            LoxObject::Break => {
+                // unwind up to and including the innermost loop.
+                while let Some(frame) = frames.pop() {
+                    if matches!(
+                        frame,
+                        Frame::While { .. } | Frame::For { .. } | Frame::ForIn { .. }
+                    ) {
+                        break;
+                    }
+                }
+                Ok(None)
+            }
+            LoxObject::Exit(v) => Ok(Some(Step::Returned(*v))),
+            _ => Ok(None),
+        };
+    }
+
+    match stmt {
+        Stmt::Yield { value, target, .. } => {
+            let value = match value {
+                Some(value) => interpreter.evaluate_in(env.clone(), value)?,
+                None => LoxObject::Nil,
+            };
+            fiber::expect_yielded(interpreter, Some(&value))?;
+            if let Some(name) = target {
+                frames.push(Frame::Receive {
+                    name: name.clone(),
+                    env,
+                });
+            }
+            return Ok(Some(Step::Yielded(value)));
+        }
+        Stmt::Block { statements } => frames.push(Frame::Block {
+            statements: statements.as_slice().into(),
+            pc: 0,
+            env: Environment::new_rc(Some(env)),
+        }),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let branch = if is_truthy(&interpreter.evaluate_in(env.clone(), condition)?) {
+                Some(then_branch)
+            } else {
+                else_branch.as_ref()
+            };
+            if let Some(branch) = branch {
+                frames.push(Frame::Block {
+                    statements: Rc::new([branch.as_ref().clone()]),
+                    pc: 0,
+                    env,
+                });
+            }
+        }
+        Stmt::While { condition, body } => frames.push(Frame::While {
+            condition: condition.clone(),
+            body: Rc::new([body.as_ref().clone()]),
+            env,
+        }),
+        Stmt::For {
+            initializer,
+            condition,
+            increment,
+            body,
+        } => {
+            let env = Environment::new_rc(Some(env));
+            frames.push(Frame::For {
+                condition: condition.clone(),
+                increment: increment.clone(),
+                body: Rc::new([body.as_ref().clone()]),
+                env: env.clone(),
+                started: false,
+            });
+            // the initializer runs first, it may yield too.
+            if let Some(initializer) = initializer {
+                frames.push(Frame::Block {
+                    statements: Rc::new([initializer.as_ref().clone()]),
+                    pc: 0,
+                    env,
+                });
+            }
+        }
+        Stmt::ForIn {
+            keyword,
+            name,
+            iterable,
+            body,
+        } => {
+            let iterable = interpreter.evaluate_in(env.clone(), iterable)?;
+            frames.push(Frame::ForIn {
+                iterator: LoxIterator::new(iterable, keyword)?,
+                keyword: keyword.clone(),
+                name: name.clone(),
+                body: Rc::new([body.as_ref().clone()]),
+                env,
+            });
+        }
+        _ => unreachable!("the resolver only allows yield in blocks, ifs and loops"),
+    }
+    Ok(None)
+}
+
+// the "next" function of a generator object, resumes it until the next
+// yield and answers {value: v, done: false}, or {value: returned, done: true}.
+// next(x) makes x the value of the "var name = yield ...;" it resumes at.
+#[derive(Debug)]
+struct GeneratorNext(Rc<Generator>);
+
+impl Callable for GeneratorNext {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn max_arity(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut LoxVisitor,
+        args: &[LoxObject],
+    ) -> Result<LoxObject, RuntimeError> {
+        let sent = args.first().cloned().unwrap_or(LoxObject::Nil);
+        let (value, done) = match self.0.resume(interpreter, sent)? {
+            Step::Yielded(value) => (value, false),
+            Step::Returned(value) => (value, true),
+        };
+        Ok(LoxObject::map(BTreeMap::from([
+            ("value".to_string(), value),
+            ("done".to_string(), LoxObject::Boolean(done)),
+        ])))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::errors::RuntimeError;
+    use crate::testing::run;
+
+    #[test]
+    fn test_generators_resume_lazily() {
+        let (_, result) = run("var started = 0;
+            fun naturals() { started = started + 1; var n = 0; while (true) { yield n; n = n + 1; } }
+            var gen = naturals();
+            if (started != 0) throw \"ran before next()\";
+
+            var out = \"\";
+            for (var n in gen) { if (n == 4) break; out = out + str(n); }
+            if (out != \"0123\" or gen.next().value != 5) throw out;
+
+            fun evens(limit) {
+                for (var i = 0; i < limit; i = i + 1) if (i % 2 == 0) yield i; else { }
+                return \"end\";
+            }
+            var g = evens(5);
+            var a = g.next(); var b = g.next(); var c = g.next(); var d = g.next();
+            if (a.value != 0 or b.value != 2 or c.value != 4 or c.done) throw \"wrong values\";
+            if (!d.done or d.value != \"end\" or !g.next().done) throw \"didn't finish\";
+
+            fun pairs(m) { for (var k in m) for (var c in k) { yield c; yield m[k]; } }
+            out = \"\";
+            for (var x in pairs({ab: 1})) out = out + str(x);
+            if (out != \"a1b1\") throw out;");
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_sending_values_in() {
+        let (_, result) = run("fun accumulate() { var total = 0; while (true) { var n = yield total; total = total + n; } }
+            var acc = accumulate();
+            acc.next(); acc.next(5);
+            var last = acc.next(10);
+            if (last.value != 15 or last.done) throw last;");
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_generator_errors() {
+        let (_, result) = run("fun failing() { yield 1; throw \"boom\"; }
+            var gen = failing(); gen.next();
+            var caught;
+            try { gen.next(); } catch (e) { caught = e; }
+            if (caught != \"boom\" or !gen.next().done) throw \"error didn't finish it\";
+
+            var self;
+            fun reentrant() { yield self.next(); }
+            self = reentrant();
+            try { self.next(); } catch (e) { caught = e.kind; }
+            if (caught != \"Error\") throw caught;");
+        assert_eq!(result, Ok(()));
+
+        match run("for (var x in 42) print x;").1 {
+            Err(e @ RuntimeError::NotIterable(..)) => {
+                assert!(e
+                    .to_string()
+                    .contains("Cannot iterate over integer value \"42\""))
+            }
+            other => panic!("expected a not iterable error, got {:?}", other),
+        }
+    }
+}
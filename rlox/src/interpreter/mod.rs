@@ -1,5 +1,8 @@
 pub mod environment;
 pub mod errors;
+pub mod generator;
+pub mod module;
 pub mod native;
 pub mod primitive;
+pub mod stdlib;
 pub mod visitor;
@@ -0,0 +1,201 @@
+use crate::interpreter::primitive::LoxObject;
+use crate::language::ast::Stmt;
+use crate::language::parser::Parser;
+use crate::language::scanner::Scanner;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+// bookkeeping for "import", modules are cached by their canonical path so
+// each file is executed at most once per interpreter.
+#[derive(Debug, Default)]
+pub struct Modules {
+    cache: HashMap<PathBuf, LoxObject>,
+    // the chain of modules currently being executed, used to detect cycles.
+    loading: Vec<PathBuf>,
+    root: Option<PathBuf>,
+}
+
+impl Modules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the script that was run directly, imports from it resolve next to it.
+    pub fn set_root(&mut self, script: &Path) {
+        self.root = script.canonicalize().ok();
+    }
+
+    // relative paths resolve next to the importing module, or the script when
+    // the import is written in the script itself.
+    pub fn resolve(&self, path: &str, importer: Option<&Path>) -> Result<PathBuf, String> {
+        let base = importer
+            .or(self.root.as_deref())
+            .and_then(|p| p.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        base.join(path)
+            .canonicalize()
+            .map_err(|e| format!("cannot resolve \"{}\": {}", path, e))
+    }
+
+    pub fn cached(&self, path: &Path) -> Option<LoxObject> {
+        self.cache.get(path).cloned()
+    }
+
+    pub fn begin(&mut self, path: PathBuf) -> Result<(), String> {
+        let is_root = self.root.as_ref() == Some(&path);
+        if is_root || self.loading.contains(&path) {
+            let mut chain: Vec<String> = self.root.iter().map(|p| display(p)).collect();
+            chain.extend(self.loading.iter().map(|p| display(p)));
+            chain.push(display(&path));
+            return Err(format!("import cycle detected: {}", chain.join(" -> ")));
+        }
+        self.loading.push(path);
+        Ok(())
+    }
+
+    // pops the module started by "begin", caching its exports when it succeeded.
+    pub fn finish(&mut self, exports: Option<LoxObject>) {
+        if let Some(path) = self.loading.pop() {
+            if let Some(exports) = exports {
+                self.cache.insert(path, exports);
+            }
+        }
+    }
+}
+
+fn display(path: &Path) -> String {
+    path.display().to_string()
+}
+
+pub fn load_source(path: &Path) -> Result<Vec<Stmt>, String> {
+    let source = read_to_string(path).map_err(|e| format!("cannot read file: {}", e))?;
+    let tokens = Scanner::new(&source)
+        .scan_tokens()
+        .map_err(|e| format!("{:?}", e))?;
+    Parser::new(tokens).parse().map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<String>>()
+            .join("\n")
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::errors::RuntimeError;
+    use crate::interpreter::visitor::LoxVisitor;
+    use crate::testing::run_with;
+    use std::path::{Path, PathBuf};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rlox-module-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // runs input as the script at root, so imports resolve next to it.
+    fn run(root: &Path, input: &str) -> (LoxVisitor, Result<(), RuntimeError>) {
+        std::fs::write(root, input).unwrap();
+        let mut interpreter = LoxVisitor::new();
+        interpreter.set_script_path(root);
+        run_with(interpreter, input)
+    }
+
+    #[test]
+    fn test_import_runs_once() {
+        let dir = temp_dir("once");
+        std::fs::write(
+            dir.join("counter.lox"),
+            "var loads = 0; fun twice(x) { return x * 2; } loads = loads + 1;",
+        )
+        .unwrap();
+
+        let input = "import \"counter.lox\" as c; from \"counter.lox\" import twice, loads;
+            import \"counter.lox\" as again;
+            if (c != again or twice(4) != 8 or again.loads != 1 or loads != 1) throw \"wrong exports\";";
+        let (_, result) = run(&dir.join("main.lox"), input);
+        assert_eq!(result, Ok(()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_exports_are_live_and_read_only() {
+        let dir = temp_dir("live");
+        std::fs::write(
+            dir.join("counter.lox"),
+            "var count = 0; fun inc() { count = count + 1; }",
+        )
+        .unwrap();
+
+        let input = "import \"counter.lox\" as u; from \"counter.lox\" import count;
+            u.inc(); u.inc();
+            if (u.count != 2) throw \"exports should read the module's variables\";
+            if (count != 0) throw \"imported names should keep their value\";
+            if (u.missing != nil) throw \"missing exports should be nil\";";
+        let (_, result) = run(&dir.join("main.lox"), input);
+        assert_eq!(result, Ok(()));
+
+        let (_, result) = run(
+            &dir.join("main.lox"),
+            "import \"counter.lox\" as u;\nu.count = 5;",
+        );
+        match result {
+            Err(e @ RuntimeError::ConstReassignment(..)) => {
+                assert_eq!(
+                    e.to_string(),
+                    "RuntimeError: Cannot reassign const \"count\" @(2:3)"
+                )
+            }
+            other => panic!("expected an assignment error, got {:?}", other),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_imports_resolve_next_to_their_module() {
+        let dir = temp_dir("relative");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("helper.lox"), "var name = \"top\";").unwrap();
+        std::fs::write(dir.join("lib/helper.lox"), "var name = \"lib\";").unwrap();
+        // the import runs when main calls load, long after lib/b.lox finished.
+        std::fs::write(
+            dir.join("lib/b.lox"),
+            "fun load() { import \"helper.lox\" as h; return h.name; }",
+        )
+        .unwrap();
+
+        let input = "from \"lib/b.lox\" import load; import \"helper.lox\" as h;
+            if (load() != \"lib\" or h.name != \"top\") throw load();";
+        let (_, result) = run(&dir.join("main.lox"), input);
+        assert_eq!(result, Ok(()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = temp_dir("errors");
+        std::fs::write(dir.join("a.lox"), "import \"b.lox\" as b;").unwrap();
+        std::fs::write(dir.join("b.lox"), "import \"a.lox\" as a;").unwrap();
+        std::fs::write(dir.join("c.lox"), "var x = 1;").unwrap();
+
+        let (_, result) = run(&dir.join("main.lox"), "\nimport \"a.lox\" as a;");
+        match result {
+            Err(e @ RuntimeError::ImportFailed(..)) => {
+                assert_eq!(e.coordinate().map(|c| c.line), Some(2));
+                assert!(e.to_string().contains("import cycle detected"));
+            }
+            other => panic!("expected an import error, got {:?}", other),
+        }
+
+        let (_, result) = run(&dir.join("main.lox"), "import \"nope.lox\" as n;");
+        assert!(matches!(result, Err(RuntimeError::ImportFailed(..))));
+
+        let (_, result) = run(&dir.join("main.lox"), "from \"c.lox\" import y;");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(_))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,11 +1,11 @@
 use crate::interpreter::environment::Environment;
 use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::generator::Generator;
 use crate::interpreter::primitive::{Callable, LoxObject};
 use crate::interpreter::visitor::LoxVisitor;
-use crate::language::ast::Stmt;
-use crate::language::token::Token;
+use crate::language::ast::{Param, Stmt};
+use crate::language::token::{Slot, Token};
 use std::cell::RefCell;
-use std::fs::read_to_string;
 use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -13,22 +13,30 @@ use std::time::{SystemTime, UNIX_EPOCH};
 #[derive(Debug)]
 pub struct LoxFunction {
     _name: Option<Token>,
-    params: Vec<Token>,
-    body: Vec<Stmt>,
+    params: Vec<Param>,
+    // shared with the function node it was created from.
+    body: Rc<[Stmt]>,
+    // the cells of the variables it captured, see Environment::capture.
     closure: Rc<RefCell<Environment>>,
+    // calling a function containing "yield" returns a generator instead.
+    is_generator: bool,
 }
 
 impl LoxFunction {
     pub fn new(
         name: Option<Token>,
-        params: Vec<Token>,
-        body: Vec<Stmt>,
-        closure: Rc<RefCell<Environment>>,
+        params: &[Param],
+        body: &Rc<[Stmt]>,
+        captures: &[Slot],
+        is_generator: bool,
+        enclosing: &Rc<RefCell<Environment>>,
     ) -> Self {
+        let closure = Environment::capture(enclosing, captures);
         Self {
             _name: name,
-            params,
-            body,
+            is_generator,
+            params: params.to_vec(),
+            body: body.clone(),
             closure,
         }
     }
@@ -36,7 +44,17 @@ impl LoxFunction {
 
 impl Callable for LoxFunction {
     fn arity(&self) -> usize {
-        self.params.len()
+        self.params
+            .iter()
+            .filter(|p| p.default.is_none() && !p.is_rest)
+            .count()
+    }
+
+    fn max_arity(&self) -> Option<usize> {
+        match self.params.last() {
+            Some(p) if p.is_rest => None,
+            _ => Some(self.params.len()),
+        }
     }
 
     fn call(
@@ -46,17 +64,68 @@ impl Callable for LoxFunction {
     ) -> Result<LoxObject, RuntimeError> {
         let fresh_env = Environment::new_rc(Some(self.closure.clone()));
 
-        for (param, value) in self.params.iter().zip(args.iter()) {
-            fresh_env
-                .borrow_mut()
-                .define(param.lexeme_or_empty(), value.clone());
+        for (i, param) in self.params.iter().enumerate() {
+            // defaults are evaluated per call, after the preceding parameters
+            // are bound so they can refer to them.
+            let value = if param.is_rest {
+                LoxObject::list(args.get(i..).unwrap_or_default().to_vec())
+            } else if let Some(arg) = args.get(i) {
+                arg.clone()
+            } else if let Some(default) = &param.default {
+                interpreter.evaluate_in(fresh_env.clone(), default)?
+            } else {
+                LoxObject::Nil
+            };
+            fresh_env.borrow_mut().define(&param.name, value);
         }
 
-        let v = interpreter.execute_block(fresh_env, &self.body)?;
-        match v {
+        if self.is_generator {
+            return Ok(Generator::object(self.body.clone(), fresh_env));
+        }
+
+        match interpreter.execute_block(fresh_env, &self.body)? {
             LoxObject::Exit(v) => Ok(*v),
-            _ => Ok(v),
+            _ => Ok(LoxObject::Nil),
+        }
+    }
+}
+
+pub type NativeFn = fn(&mut LoxVisitor, &[LoxObject]) -> Result<LoxObject, RuntimeError>;
+
+// a native function implemented in rust, the function is responsible for
+// validating the types of its arguments but arity is checked before it is invoked.
+#[derive(Debug)]
+pub struct NativeFunction {
+    name: &'static str,
+    arity: usize,
+    func: NativeFn,
+}
+
+impl NativeFunction {
+    pub fn new(name: &'static str, arity: usize, func: NativeFn) -> Self {
+        Self { name, arity, func }
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut LoxVisitor,
+        args: &[LoxObject],
+    ) -> Result<LoxObject, RuntimeError> {
+        if args.len() != self.arity {
+            return Err(RuntimeError::Native(format!(
+                "\"{}\" expected {} arguments but got {}",
+                self.name,
+                self.arity,
+                args.len()
+            )));
         }
+        (self.func)(interpreter, args)
     }
 }
 
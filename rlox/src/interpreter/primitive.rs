@@ -1,47 +1,197 @@
+use crate::interpreter::environment::Environment;
 use crate::interpreter::errors::RuntimeError;
 use crate::interpreter::visitor::LoxVisitor;
 use crate::language::token::Literal;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt;
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum LoxObject {
+    // integer literals and arithmetic on integers stay exact, anything
+    // involving a float (or "/") produces a float.
+    Integer(i64),
     Number(f64),
     String(String),
     Boolean(bool),
     Nil,
+    // control flow markers, a return carrying its value and a break.
     Exit(Box<LoxObject>),
+    Break,
     Function(Rc<dyn Callable>),
+    List(Rc<RefCell<Vec<LoxObject>>>),
+    Map(Rc<RefCell<BTreeMap<String, LoxObject>>>),
+    // the queue of values sent to a channel and not received yet.
+    Channel(Rc<RefCell<VecDeque<LoxObject>>>),
+    // an imported module, its properties read the module's variables as
+    // they are now.
+    Module(Rc<RefCell<Environment>>),
+}
+
+impl LoxObject {
+    pub fn list(values: Vec<LoxObject>) -> Self {
+        LoxObject::List(Rc::new(RefCell::new(values)))
+    }
+
+    pub fn map(entries: BTreeMap<String, LoxObject>) -> Self {
+        LoxObject::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    // integers and floats alike, as a float.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            LoxObject::Integer(i) => Some(*i as f64),
+            LoxObject::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LoxObject::Integer(_) => "integer",
+            LoxObject::Number(_) => "number",
+            LoxObject::String(_) => "string",
+            LoxObject::Boolean(_) => "boolean",
+            LoxObject::Nil | LoxObject::Break => "nil",
+            LoxObject::Exit(v) => v.type_name(),
+            LoxObject::Function(_) => "function",
+            LoxObject::List(_) => "list",
+            LoxObject::Map(_) => "map",
+            LoxObject::Channel(_) => "channel",
+            LoxObject::Module(_) => "module",
+        }
+    }
 }
 
 impl fmt::Display for LoxObject {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            LoxObject::Number(value) => write!(f, "{}", value),
-            LoxObject::String(value) => write!(f, "{}", value),
-            LoxObject::Boolean(value) => write!(f, "{}", value),
-            LoxObject::Nil => write!(f, "nil"),
-            LoxObject::Exit(v) => write!(f, "{}", v),
-            LoxObject::Function(_) => write!(f, "f()[__object__]"),
+        write_value(f, self, &mut Vec::new())
+    }
+}
+
+// "seen" holds the collections currently being written, a collection that
+// contains itself is written as "[...]" or "{...}" where it repeats.
+fn write_value(f: &mut fmt::Formatter, v: &LoxObject, seen: &mut Vec<*const ()>) -> fmt::Result {
+    match v {
+        LoxObject::Integer(value) => write!(f, "{}", value),
+        LoxObject::Number(value) => write!(f, "{}", value),
+        LoxObject::String(value) => write!(f, "{}", value),
+        LoxObject::Boolean(value) => write!(f, "{}", value),
+        LoxObject::Nil | LoxObject::Break => write!(f, "nil"),
+        LoxObject::Exit(v) => write_value(f, v, seen),
+        LoxObject::Function(_) => write!(f, "f()[__object__]"),
+        LoxObject::Channel(_) => write!(f, "chan[__object__]"),
+        LoxObject::Module(_) => write!(f, "module[__object__]"),
+        LoxObject::List(values) => {
+            let ptr = values.as_ptr() as *const ();
+            if seen.contains(&ptr) {
+                return write!(f, "[...]");
+            }
+            seen.push(ptr);
+            write!(f, "[")?;
+            for (i, v) in values.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_nested(f, v, seen)?;
+            }
+            seen.pop();
+            write!(f, "]")
+        }
+        LoxObject::Map(entries) => {
+            let ptr = entries.as_ptr() as *const ();
+            if seen.contains(&ptr) {
+                return write!(f, "{{...}}");
+            }
+            seen.push(ptr);
+            write!(f, "{{")?;
+            for (i, (k, v)) in entries.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{:?}: ", k)?;
+                write_nested(f, v, seen)?;
+            }
+            seen.pop();
+            write!(f, "}}")
         }
     }
 }
 
+// values inside of a collection quote their strings so "[1, \"1\"]" is unambiguous.
+fn write_nested(f: &mut fmt::Formatter, v: &LoxObject, seen: &mut Vec<*const ()>) -> fmt::Result {
+    match v {
+        LoxObject::String(s) => write!(f, "{:?}", s),
+        other => write_value(f, other, seen),
+    }
+}
+
 impl PartialEq for LoxObject {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (LoxObject::Number(n1), LoxObject::Number(n2)) => n1 == n2,
-            (LoxObject::String(s1), LoxObject::String(s2)) => s1 == s2,
-            (LoxObject::Boolean(b1), LoxObject::Boolean(b2)) => b1 == b2,
-            (LoxObject::Nil, LoxObject::Nil) => true,
-            _ => false,
+        equals(self, other, &mut Vec::new())
+    }
+}
+
+// "seen" holds the pairs of collections currently being compared, meeting one
+// of them again means both sides are cyclic and they're compared by identity.
+fn equals(a: &LoxObject, b: &LoxObject, seen: &mut Vec<(*const (), *const ())>) -> bool {
+    match (a, b) {
+        (LoxObject::Integer(i1), LoxObject::Integer(i2)) => i1 == i2,
+        (LoxObject::Number(n1), LoxObject::Number(n2)) => n1 == n2,
+        // 1 == 1.0, without rounding large integers through a float.
+        (LoxObject::Integer(i), LoxObject::Number(n))
+        | (LoxObject::Number(n), LoxObject::Integer(i)) => {
+            n.fract() == 0.0 && n.abs() < i64::MAX as f64 && *n as i64 == *i
+        }
+        (LoxObject::String(s1), LoxObject::String(s2)) => s1 == s2,
+        (LoxObject::Boolean(b1), LoxObject::Boolean(b2)) => b1 == b2,
+        (LoxObject::Nil, LoxObject::Nil) => true,
+        (LoxObject::List(l1), LoxObject::List(l2)) => {
+            if Rc::ptr_eq(l1, l2) {
+                return true;
+            }
+            let pair = (l1.as_ptr() as *const (), l2.as_ptr() as *const ());
+            if seen.contains(&pair) {
+                return false;
+            }
+            seen.push(pair);
+            let (l1, l2) = (l1.borrow(), l2.borrow());
+            let equal =
+                l1.len() == l2.len() && l1.iter().zip(l2.iter()).all(|(a, b)| equals(a, b, seen));
+            seen.pop();
+            equal
         }
+        (LoxObject::Map(m1), LoxObject::Map(m2)) => {
+            if Rc::ptr_eq(m1, m2) {
+                return true;
+            }
+            let pair = (m1.as_ptr() as *const (), m2.as_ptr() as *const ());
+            if seen.contains(&pair) {
+                return false;
+            }
+            seen.push(pair);
+            let (m1, m2) = (m1.borrow(), m2.borrow());
+            let equal = m1.len() == m2.len()
+                && m1
+                    .iter()
+                    .zip(m2.iter())
+                    .all(|((k1, v1), (k2, v2))| k1 == k2 && equals(v1, v2, seen));
+            seen.pop();
+            equal
+        }
+        // functions, channels and modules are only ever equal to themselves.
+        (LoxObject::Function(f1), LoxObject::Function(f2)) => Rc::ptr_eq(f1, f2),
+        (LoxObject::Channel(c1), LoxObject::Channel(c2)) => Rc::ptr_eq(c1, c2),
+        (LoxObject::Module(m1), LoxObject::Module(m2)) => Rc::ptr_eq(m1, m2),
+        _ => false,
     }
 }
 
 impl From<Literal> for LoxObject {
     fn from(v: Literal) -> Self {
         match v {
+            Literal::Integer(i) => LoxObject::Integer(i),
             Literal::Number(f) => LoxObject::Number(f),
             Literal::String(s) => LoxObject::String(s),
             Literal::Boolean(b) => LoxObject::Boolean(b),
@@ -60,5 +210,11 @@ where
         args: &[LoxObject],
     ) -> Result<LoxObject, RuntimeError>;
 
+    // the minimum number of arguments.
     fn arity(&self) -> usize;
+
+    // the maximum number of arguments, None when it takes any number more.
+    fn max_arity(&self) -> Option<usize> {
+        Some(self.arity())
+    }
 }
@@ -0,0 +1,25 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::LoxObject;
+use crate::interpreter::stdlib::{define_native, invalid_argument};
+use crate::interpreter::visitor::LoxVisitor;
+
+type NativeResult = Result<LoxObject, RuntimeError>;
+
+pub fn register(env: &mut Environment) {
+    define_native(env, "keys", 1, keys);
+}
+
+// the keys of a map in the same (sorted) order they are displayed in.
+fn keys(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    match &args[0] {
+        LoxObject::Map(entries) => Ok(LoxObject::list(
+            entries
+                .borrow()
+                .keys()
+                .map(|k| LoxObject::String(k.clone()))
+                .collect(),
+        )),
+        other => Err(invalid_argument("keys", 0, "map", other)),
+    }
+}
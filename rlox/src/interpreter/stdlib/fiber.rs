@@ -0,0 +1,443 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::{Callable, LoxObject};
+use crate::interpreter::stdlib::{define_native, expect_number, invalid_argument};
+use crate::interpreter::visitor::LoxVisitor;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+type NativeResult = Result<LoxObject, RuntimeError>;
+
+// the "next" function of a spawned generator.
+type Fiber = Rc<dyn Callable>;
+
+type Channel = Rc<RefCell<VecDeque<LoxObject>>>;
+
+// cooperative, single threaded fibers built on generators. A fiber only gives
+// up control at a "yield", handing the scheduler a request: nil or yield_now()
+// to let the others run, sleep(seconds) to wait and recv(channel) to wait for
+// a value, which "var x = yield recv(ch);" then receives. Time is simulated,
+// sleeping just moves a virtual clock forward once nothing else can run, so
+// a program always interleaves the same way.
+//
+// Fibers only start once the script itself has finished, so the script can
+// spawn fibers and send to channels but never wait on them: a "recv" on the
+// script's own path raises an error rather than blocking forever. Outside of
+// a running fiber sleep, recv and yield_now have nothing to hand their
+// request to, and inside of one a request has to be the operand of a "yield",
+// so both raise an error too.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    // fibers that can run, with the value to resume them with.
+    ready: VecDeque<(Fiber, LoxObject)>,
+    // ordered by wake time, ties in the order they went to sleep.
+    sleeping: Vec<(f64, Fiber)>,
+    receiving: Vec<(Fiber, Channel)>,
+    clock: f64,
+    // whether a fiber is being run right now.
+    running: bool,
+    // the request the running fiber asked for and hasn't yielded yet.
+    unyielded: Option<(&'static str, LoxObject)>,
+}
+
+impl Scheduler {
+    fn spawn(&mut self, fiber: Fiber) {
+        self.ready.push_back((fiber, LoxObject::Nil));
+    }
+
+    // puts a fiber that yielded the given request back in line.
+    fn park(&mut self, fiber: Fiber, request: &LoxObject) {
+        let LoxObject::Map(entries) = request else {
+            self.ready.push_back((fiber, LoxObject::Nil));
+            return;
+        };
+        let entries = entries.borrow();
+
+        match (
+            entries.get("request"),
+            entries.get("seconds"),
+            entries.get("channel"),
+        ) {
+            (Some(LoxObject::String(kind)), Some(seconds), _) if kind == "sleep" => {
+                let wake = self.clock + seconds.as_number().unwrap_or(0.0);
+                let at = self.sleeping.partition_point(|(t, _)| *t <= wake);
+                self.sleeping.insert(at, (wake, fiber));
+            }
+            (Some(LoxObject::String(kind)), _, Some(LoxObject::Channel(channel)))
+                if kind == "recv" =>
+            {
+                self.receiving.push((fiber, channel.clone()));
+            }
+            _ => self.ready.push_back((fiber, LoxObject::Nil)),
+        }
+    }
+
+    // the next fiber to run, or None once every fiber has finished.
+    fn next(&mut self) -> Result<Option<(Fiber, LoxObject)>, RuntimeError> {
+        // receivers are woken in the order they started waiting.
+        let mut i = 0;
+        while i < self.receiving.len() {
+            if self.receiving[i].1.borrow().is_empty() {
+                i += 1;
+                continue;
+            }
+            let (fiber, channel) = self.receiving.remove(i);
+            let value = channel.borrow_mut().pop_front().unwrap_or(LoxObject::Nil);
+            self.ready.push_back((fiber, value));
+        }
+
+        if self.ready.is_empty() && !self.sleeping.is_empty() {
+            self.clock = self.sleeping[0].0;
+            let woken = self.sleeping.partition_point(|(t, _)| *t <= self.clock);
+            for (_, fiber) in self.sleeping.drain(..woken) {
+                self.ready.push_back((fiber, LoxObject::Nil));
+            }
+        }
+
+        match self.ready.pop_front() {
+            Some(next) => Ok(Some(next)),
+            None if self.receiving.is_empty() => Ok(None),
+            None => Err(RuntimeError::Deadlock(self.receiving.len())),
+        }
+    }
+}
+
+// runs every spawned fiber to completion, the interpreter calls this once the
+// script itself is done.
+pub fn run(interpreter: &mut LoxVisitor) -> Result<(), RuntimeError> {
+    let result = run_fibers(interpreter);
+    if result.is_err() {
+        // a failed fiber takes the rest down with it.
+        *interpreter.scheduler() = Scheduler::default();
+    }
+    result
+}
+
+fn run_fibers(interpreter: &mut LoxVisitor) -> Result<(), RuntimeError> {
+    while let Some((fiber, value)) = interpreter.scheduler().next()? {
+        interpreter.scheduler().running = true;
+        let step = fiber.call(interpreter, std::slice::from_ref(&value))?;
+        interpreter.scheduler().running = false;
+        let LoxObject::Map(step) = step else {
+            expect_yielded(interpreter, None)?;
+            continue;
+        };
+        let (request, done) = {
+            let step = step.borrow();
+            (
+                step.get("value").cloned().unwrap_or(LoxObject::Nil),
+                step.get("done") == Some(&LoxObject::Boolean(true)),
+            )
+        };
+        expect_yielded(interpreter, Some(&request))?;
+        if !done {
+            interpreter.scheduler().park(fiber, &request);
+        }
+    }
+    Ok(())
+}
+
+pub fn register(env: &mut Environment) {
+    define_native(env, "spawn", 1, spawn);
+    define_native(env, "yield_now", 0, yield_now);
+    define_native(env, "sleep", 1, sleep);
+    define_native(env, "chan", 0, chan);
+    define_native(env, "send", 2, send);
+    define_native(env, "recv", 1, recv);
+}
+
+// builds the request for func, which the running fiber has to yield before
+// it does anything else.
+fn request(
+    interpreter: &mut LoxVisitor,
+    func: &'static str,
+    kind: &str,
+    argument: Option<(&str, LoxObject)>,
+) -> NativeResult {
+    if !interpreter.scheduler().running {
+        return Err(RuntimeError::Native(format!(
+            "\"{}\" can only be used inside of a spawned fiber, as \"yield {}(...)\"",
+            func, func
+        )));
+    }
+    expect_yielded(interpreter, None)?;
+
+    let mut entries = BTreeMap::from([("request".to_string(), LoxObject::String(kind.into()))]);
+    if let Some((key, value)) = argument {
+        entries.insert(key.to_string(), value);
+    }
+    let request = LoxObject::map(entries);
+    interpreter.scheduler().unyielded = Some((func, request.clone()));
+    Ok(request)
+}
+
+// a generator calls this after each statement it runs and with each value it
+// yields, so a request that isn't the operand of a "yield" is reported before
+// the fiber carries on as if it had waited.
+pub fn expect_yielded(
+    interpreter: &mut LoxVisitor,
+    yielded: Option<&LoxObject>,
+) -> Result<(), RuntimeError> {
+    let Some((func, request)) = interpreter.scheduler().unyielded.take() else {
+        return Ok(());
+    };
+    match (yielded, request) {
+        (Some(LoxObject::Map(yielded)), LoxObject::Map(request))
+            if Rc::ptr_eq(yielded, &request) =>
+        {
+            Ok(())
+        }
+        _ => Err(RuntimeError::Native(format!(
+            "\"{}\" has to be yielded to wait, as \"yield {}(...)\"",
+            func, func
+        ))),
+    }
+}
+
+fn expect_channel<'a>(
+    func: &'static str,
+    args: &'a [LoxObject],
+    position: usize,
+) -> Result<&'a Channel, RuntimeError> {
+    match &args[position] {
+        LoxObject::Channel(queue) => Ok(queue),
+        other => Err(invalid_argument(func, position, "channel", other)),
+    }
+}
+
+// spawn(fn) calls fn and schedules the generator it returns, so fn is either
+// a generator function or returns the generator of one.
+fn spawn(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let LoxObject::Function(f) = &args[0] else {
+        return Err(invalid_argument("spawn", 0, "function", &args[0]));
+    };
+    let fiber = f.call(interpreter, &[])?;
+
+    let next = match &fiber {
+        LoxObject::Map(entries) => match entries.borrow().get("next") {
+            Some(LoxObject::Function(next)) => Some(next.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    match next {
+        Some(next) => interpreter.scheduler().spawn(next),
+        None => {
+            return Err(RuntimeError::Native(format!(
+                "\"spawn\" expects a function returning a generator, got {} \"{}\"",
+                fiber.type_name(),
+                fiber
+            )))
+        }
+    }
+    Ok(fiber)
+}
+
+fn yield_now(interpreter: &mut LoxVisitor, _: &[LoxObject]) -> NativeResult {
+    request(interpreter, "yield_now", "yield", None)
+}
+
+fn sleep(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let seconds = expect_number("sleep", args, 0)?;
+    if seconds.is_nan() || seconds < 0.0 {
+        return Err(invalid_argument(
+            "sleep",
+            0,
+            "non negative number",
+            &args[0],
+        ));
+    }
+    request(
+        interpreter,
+        "sleep",
+        "sleep",
+        Some(("seconds", args[0].clone())),
+    )
+}
+
+// channels are unbounded queues, so sending never has to wait.
+fn chan(_: &mut LoxVisitor, _: &[LoxObject]) -> NativeResult {
+    Ok(LoxObject::Channel(Rc::default()))
+}
+
+fn send(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    expect_channel("send", args, 0)?
+        .borrow_mut()
+        .push_back(args[1].clone());
+    Ok(LoxObject::Nil)
+}
+
+fn recv(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    expect_channel("recv", args, 0)?;
+    request(
+        interpreter,
+        "recv",
+        "recv",
+        Some(("channel", args[0].clone())),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::run;
+
+    #[test]
+    fn test_fibers_interleave_deterministically() {
+        let (_, result) = run(
+            "var log = \"\";
+            fun ticker(name, every, times) {
+                for (var i = 0; i < times; i = i + 1) {
+                    yield sleep(every);
+                    log = log + name;
+                }
+            }
+            spawn(() => ticker(\"a\", 2, 3));
+            spawn(() => ticker(\"b\", 3, 2));
+            fun polite() { log = log + \"1\"; yield; log = log + \"2\"; yield yield_now(); log = log + \"3\"; }
+            spawn(polite);
+            if (log != \"\") throw \"fibers ran before the script finished\";
+            var check = fun () { if (log != \"123ababa\") throw log; };
+            spawn(fun () { yield sleep(100); check(); });",
+        );
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_channels() {
+        let (_, result) = run("var jobs = chan(); var results = chan();
+            fun worker() {
+                while (true) {
+                    var job = yield recv(jobs);
+                    if (job == nil) break;
+                    send(results, job * job);
+                }
+            }
+            fun collector(count) {
+                var total = 0;
+                for (var i = 0; i < count; i = i + 1) {
+                    var r = yield recv(results);
+                    total = total + r;
+                }
+                if (total != 30) throw total;
+            }
+            spawn(worker); spawn(worker);
+            spawn(() => collector(4));
+            for (var x in [1, 2, 3, 4]) send(jobs, x);
+            send(jobs, nil); send(jobs, nil);");
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_fibers_run_after_main() {
+        // the script sends before any fiber has started to wait.
+        let (_, result) = run("var log = \"\";
+            var ch = chan();
+            spawn(fun () { var v = yield recv(ch); log = log + v; });
+            send(ch, \"b\");
+            log = log + \"a\";
+            spawn(fun () { yield sleep(1); if (log != \"ab\") throw log; });");
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_requests_outside_of_fibers() {
+        for (input, func) in [
+            ("sleep(5);", "sleep"),
+            ("yield_now();", "yield_now"),
+            ("recv(chan());", "recv"),
+            // a fiber's helper has the same problem as the script itself.
+            ("fun nap() { sleep(1); } nap();", "sleep"),
+        ] {
+            match run(input).1 {
+                Err(e @ RuntimeError::AtCall(..)) => assert!(
+                    e.message().starts_with(&format!(
+                        "\"{}\" can only be used inside of a spawned fiber",
+                        func
+                    )),
+                    "{}",
+                    e
+                ),
+                other => panic!("expected an error for {}, got {:?}", input, other),
+            }
+        }
+
+        let (_, plain) = run("spawn(fun () { return 1; });");
+        assert!(matches!(plain, Err(RuntimeError::AtCall(..))));
+
+        let (_, list) = run("send([], 1);");
+        match list {
+            Err(RuntimeError::AtCall(e, _)) => assert!(matches!(
+                *e,
+                RuntimeError::InvalidArgument("send", 1, "channel", _)
+            )),
+            other => panic!("expected an invalid argument, got {:?}", other),
+        }
+        let (_, channel) = run("if (str(chan()) != \"chan[__object__]\") throw chan();");
+        assert_eq!(channel, Ok(()));
+    }
+
+    #[test]
+    fn test_forgotten_yield() {
+        for (input, func) in [
+            ("spawn(fun () { sleep(5); yield; });", "sleep"),
+            ("spawn(fun () { yield_now(); yield; });", "yield_now"),
+            (
+                "var ch = chan(); send(ch, 1); spawn(fun () { var v = recv(ch); throw v; yield; });",
+                "recv",
+            ),
+            // a request kept for later still isn't waited on where it is made.
+            ("spawn(fun () { var r = sleep(1); yield r; });", "sleep"),
+            ("spawn(fun () { sleep(1); sleep(2); yield; });", "sleep"),
+            ("spawn(fun () { return sleep(1); yield; });", "sleep"),
+        ] {
+            match run(input).1 {
+                Err(e) => assert_eq!(
+                    e.message(),
+                    format!("\"{}\" has to be yielded to wait, as \"yield {}(...)\"", func, func),
+                    "{}",
+                    input
+                ),
+                other => panic!("expected an error for {}, got {:?}", input, other),
+            }
+        }
+
+        let (_, yielded) = run("var ch = chan(); send(ch, 1);
+            spawn(fun () { yield sleep(1); yield yield_now(); var got = yield recv(ch); if (got != 1) throw got; });");
+        assert_eq!(yielded, Ok(()));
+    }
+
+    #[test]
+    fn test_main_cannot_wait_on_fibers() {
+        // the fiber would send, but it only runs once the script is done.
+        let (_, result) = run("var ch = chan();
+            spawn(fun () { send(ch, 1); yield; });
+            recv(ch);");
+        match result {
+            Err(e) => assert!(e
+                .message()
+                .starts_with("\"recv\" can only be used inside of a spawned fiber")),
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_deadlock_and_errors() {
+        let (_, deadlock) = run("var a = chan(); var b = chan();
+            spawn(fun () { var x = yield recv(a); send(b, x); });
+            spawn(fun () { var y = yield recv(b); send(a, y); });");
+        match deadlock {
+            Err(e @ RuntimeError::Deadlock(2)) => assert_eq!(e.kind(), "DeadlockError"),
+            other => panic!("expected a deadlock, got {:?}", other),
+        }
+
+        let (_, failed) = run("spawn(fun () { yield; throw \"boom\"; });");
+        assert!(matches!(failed, Err(RuntimeError::Thrown(..))));
+
+        let mut interpreter = LoxVisitor::new();
+        let negative = LoxObject::Number(-1.0);
+        assert!(sleep(&mut interpreter, &[negative]).is_err());
+        assert!(recv(&mut interpreter, &[LoxObject::Nil]).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::LoxObject;
+use crate::interpreter::stdlib::{define_native, expect_string};
+use crate::interpreter::visitor::LoxVisitor;
+use std::fs::{read_to_string, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+type NativeResult = Result<LoxObject, RuntimeError>;
+
+pub fn register(env: &mut Environment) {
+    define_native(env, "read_file", 1, read_file);
+    define_native(env, "write_file", 2, write_file);
+    define_native(env, "append_file", 2, append_file);
+    define_native(env, "file_exists", 1, file_exists);
+    define_native(env, "read_line", 0, read_line);
+    define_native(env, "input", 1, input);
+}
+
+fn require_filesystem(interpreter: &LoxVisitor, func: &'static str) -> Result<(), RuntimeError> {
+    if interpreter.capabilities().filesystem {
+        Ok(())
+    } else {
+        Err(RuntimeError::CapabilityDisabled(func, "filesystem"))
+    }
+}
+
+fn require_stdin(interpreter: &LoxVisitor, func: &'static str) -> Result<(), RuntimeError> {
+    if interpreter.capabilities().stdin {
+        Ok(())
+    } else {
+        Err(RuntimeError::CapabilityDisabled(func, "stdin"))
+    }
+}
+
+fn io_error(func: &'static str, path: &str, e: io::Error) -> RuntimeError {
+    RuntimeError::Native(format!("\"{}\" failed on \"{}\": {}", func, path, e))
+}
+
+fn read_file(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    require_filesystem(interpreter, "read_file")?;
+    let path = expect_string("read_file", args, 0)?;
+    read_to_string(path)
+        .map(LoxObject::String)
+        .map_err(|e| io_error("read_file", path, e))
+}
+
+fn write_file(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    require_filesystem(interpreter, "write_file")?;
+    let path = expect_string("write_file", args, 0)?;
+    let contents = expect_string("write_file", args, 1)?;
+    std::fs::write(path, contents).map_err(|e| io_error("write_file", path, e))?;
+    Ok(LoxObject::Nil)
+}
+
+fn append_file(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    require_filesystem(interpreter, "append_file")?;
+    let path = expect_string("append_file", args, 0)?;
+    let contents = expect_string("append_file", args, 1)?;
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| io_error("append_file", path, e))?;
+    Ok(LoxObject::Nil)
+}
+
+fn file_exists(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    require_filesystem(interpreter, "file_exists")?;
+    let path = expect_string("file_exists", args, 0)?;
+    Ok(LoxObject::Boolean(Path::new(path).is_file()))
+}
+
+// reads one line from stdin without its line ending, nil once stdin is exhausted.
+fn read_line(interpreter: &mut LoxVisitor, _: &[LoxObject]) -> NativeResult {
+    require_stdin(interpreter, "read_line")?;
+    next_line("read_line")
+}
+
+fn input(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    require_stdin(interpreter, "input")?;
+    let prompt = expect_string("input", args, 0)?;
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", prompt)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| io_error("input", "stdout", e))?;
+    next_line("input")
+}
+
+fn next_line(func: &'static str) -> NativeResult {
+    let mut line = String::new();
+    let read = io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| io_error(func, "stdin", e))?;
+
+    if read == 0 {
+        return Ok(LoxObject::Nil);
+    }
+
+    let trimmed = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(trimmed);
+    Ok(LoxObject::String(line))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::visitor::Capabilities;
+    use crate::testing::s;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rlox-io-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test_write_append_read() {
+        let mut interpreter = LoxVisitor::new();
+        let path = temp_path("write");
+
+        write_file(&mut interpreter, &[s(&path), s("hello")]).unwrap();
+        append_file(&mut interpreter, &[s(&path), s(" world")]).unwrap();
+        assert_eq!(
+            file_exists(&mut interpreter, &[s(&path)]),
+            Ok(LoxObject::Boolean(true))
+        );
+        assert_eq!(
+            read_file(&mut interpreter, &[s(&path)]),
+            Ok(s("hello world"))
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            file_exists(&mut interpreter, &[s(&path)]),
+            Ok(LoxObject::Boolean(false))
+        );
+        assert!(read_file(&mut interpreter, &[s(&path)]).is_err());
+    }
+
+    #[test]
+    fn test_capabilities_disabled() {
+        let mut interpreter = LoxVisitor::with_capabilities(Capabilities::none());
+        let path = temp_path("disabled");
+
+        assert_eq!(
+            write_file(&mut interpreter, &[s(&path), s("nope")]),
+            Err(RuntimeError::CapabilityDisabled("write_file", "filesystem"))
+        );
+        assert_eq!(
+            read_line(&mut interpreter, &[]),
+            Err(RuntimeError::CapabilityDisabled("read_line", "stdin"))
+        );
+        assert!(!Path::new(&path).exists());
+    }
+}
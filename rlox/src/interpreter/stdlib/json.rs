@@ -0,0 +1,533 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::LoxObject;
+use crate::interpreter::stdlib::{define_native, expect_integer, expect_string, invalid_argument};
+use crate::interpreter::visitor::LoxVisitor;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+type NativeResult = Result<LoxObject, RuntimeError>;
+
+// how deeply arrays and objects may nest before json_parse gives up, well
+// short of overflowing the stack while parsing or printing the result.
+const MAX_DEPTH: usize = 128;
+
+pub fn register(env: &mut Environment) {
+    define_native(env, "json_parse", 1, json_parse);
+    define_native(env, "json_stringify", 2, json_stringify);
+}
+
+fn json_parse(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let src = expect_string("json_parse", args, 0)?;
+    let mut parser = JsonParser::new(src);
+    parser.skip_whitespace();
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+// json_stringify(value, indent), an indent of 0 or nil produces compact output.
+fn json_stringify(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let indent = match &args[1] {
+        LoxObject::Nil => 0,
+        _ => usize::try_from(expect_integer("json_stringify", args, 1)?)
+            .map_err(|_| invalid_argument("json_stringify", 1, "indent", &args[1]))?,
+    };
+    let mut writer = JsonWriter {
+        out: String::new(),
+        indent,
+        stack: Vec::new(),
+    };
+    writer.value(&args[0], 0)?;
+    Ok(LoxObject::String(writer.out))
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn new(src: &'a str) -> Self {
+        Self {
+            chars: src.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    // the position is within the JSON text, the call site's coordinate is
+    // added when the error leaves the native.
+    fn error(&self, msg: &str) -> RuntimeError {
+        RuntimeError::Native(format!(
+            "\"json_parse\" {} at line {}, column {}",
+            msg, self.line, self.column
+        ))
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn take(&mut self) -> Option<char> {
+        let ch = self.chars.next()?;
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(ch)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), RuntimeError> {
+        match self.peek() {
+            Some(ch) if ch == expected => {
+                self.take();
+                Ok(())
+            }
+            Some(ch) => Err(self.error(&format!("expected '{}' but found '{}'", expected, ch))),
+            None => Err(self.error(&format!("expected '{}' but found end of input", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.take();
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> NativeResult {
+        match self.peek() {
+            Some('{' | '[') if depth >= MAX_DEPTH => {
+                Err(self.error(&format!("nesting is deeper than {} levels", MAX_DEPTH)))
+            }
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some('"') => Ok(LoxObject::String(self.string()?)),
+            Some('t') => self.keyword("true", LoxObject::Boolean(true)),
+            Some('f') => self.keyword("false", LoxObject::Boolean(false)),
+            Some('n') => self.keyword("null", LoxObject::Nil),
+            Some(ch) if ch == '-' || ch.is_ascii_digit() => self.number(),
+            Some(ch) => Err(self.error(&format!("unexpected character '{}'", ch))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: LoxObject) -> NativeResult {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error(&format!("invalid literal, expected \"{}\"", word)));
+            }
+            self.take();
+        }
+        Ok(value)
+    }
+
+    // -?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?
+    fn number(&mut self) -> NativeResult {
+        let mut lexeme = String::new();
+        if self.peek() == Some('-') {
+            lexeme.push('-');
+            self.take();
+        }
+        if self.peek() == Some('0') {
+            lexeme.push('0');
+            self.take();
+            if self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+                return Err(self.error("leading zero in number"));
+            }
+        } else {
+            self.digits(&mut lexeme)?;
+        }
+        if self.peek() == Some('.') {
+            lexeme.push('.');
+            self.take();
+            self.digits(&mut lexeme)?;
+        }
+        if let Some(e @ ('e' | 'E')) = self.peek() {
+            lexeme.push(e);
+            self.take();
+            if let Some(sign @ ('+' | '-')) = self.peek() {
+                lexeme.push(sign);
+                self.take();
+            }
+            self.digits(&mut lexeme)?;
+        }
+        // numbers without a fraction or exponent are integers, unless they're too large.
+        if let Ok(i) = lexeme.parse::<i64>() {
+            return Ok(LoxObject::Integer(i));
+        }
+        lexeme
+            .parse::<f64>()
+            .map(LoxObject::Number)
+            .map_err(|_| self.error(&format!("invalid number \"{}\"", lexeme)))
+    }
+
+    // one or more digits.
+    fn digits(&mut self, lexeme: &mut String) -> Result<(), RuntimeError> {
+        if !self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+            return Err(self.error("expected a digit in number"));
+        }
+        while let Some(ch) = self.peek().filter(char::is_ascii_digit) {
+            lexeme.push(ch);
+            self.take();
+        }
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, RuntimeError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.take() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.escape()?),
+                Some(ch) if (ch as u32) < 0x20 => {
+                    return Err(self.error("control character in string"))
+                }
+                Some(ch) => s.push(ch),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, RuntimeError> {
+        match self.take() {
+            Some('"') => Ok('"'),
+            Some('\\') => Ok('\\'),
+            Some('/') => Ok('/'),
+            Some('b') => Ok('\u{8}'),
+            Some('f') => Ok('\u{c}'),
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('u') => {
+                let high = self.hex4()?;
+                if !(0xD800..0xDC00).contains(&high) {
+                    return char::from_u32(high)
+                        .ok_or_else(|| self.error("invalid unicode escape"));
+                }
+                // a surrogate pair spells a code point outside the basic plane.
+                self.expect('\\')?;
+                self.expect('u')?;
+                let low = self.hex4()?;
+                if !(0xDC00..0xE000).contains(&low) {
+                    return Err(self.error("invalid unicode surrogate pair"));
+                }
+                char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+                    .ok_or_else(|| self.error("invalid unicode escape"))
+            }
+            Some(ch) => Err(self.error(&format!("invalid escape '\\{}'", ch))),
+            None => Err(self.error("unterminated string")),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, RuntimeError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .peek()
+                .and_then(|ch| ch.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape"))?;
+            self.take();
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self, depth: usize) -> NativeResult {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.take();
+            return Ok(LoxObject::list(values));
+        }
+        loop {
+            self.skip_whitespace();
+            values.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.take();
+                }
+                Some(']') => {
+                    self.take();
+                    return Ok(LoxObject::list(values));
+                }
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> NativeResult {
+        self.expect('{')?;
+        let mut entries = BTreeMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.take();
+            return Ok(LoxObject::map(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a string key in object"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            entries.insert(key, self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.take();
+                }
+                Some('}') => {
+                    self.take();
+                    return Ok(LoxObject::map(entries));
+                }
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+}
+
+struct JsonWriter {
+    out: String,
+    indent: usize,
+    // addresses of the collections currently being written, to detect cycles.
+    stack: Vec<*const ()>,
+}
+
+impl JsonWriter {
+    fn error(msg: String) -> RuntimeError {
+        RuntimeError::Native(format!("\"json_stringify\" {}", msg))
+    }
+
+    fn newline(&mut self, depth: usize) {
+        if self.indent > 0 {
+            self.out.push('\n');
+            self.out.push_str(&" ".repeat(self.indent * depth));
+        }
+    }
+
+    fn enter(&mut self, ptr: *const ()) -> Result<(), RuntimeError> {
+        if self.stack.contains(&ptr) {
+            return Err(Self::error(
+                "cannot serialize a cyclic structure".to_string(),
+            ));
+        }
+        self.stack.push(ptr);
+        Ok(())
+    }
+
+    fn value(&mut self, value: &LoxObject, depth: usize) -> Result<(), RuntimeError> {
+        match value {
+            LoxObject::Nil => self.out.push_str("null"),
+            LoxObject::Boolean(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            LoxObject::Integer(i) => {
+                let _ = write!(self.out, "{}", i);
+            }
+            LoxObject::Number(n) if n.is_finite() => {
+                let _ = write!(self.out, "{}", n);
+            }
+            LoxObject::Number(n) => {
+                return Err(Self::error(format!("cannot serialize the number {}", n)))
+            }
+            LoxObject::String(s) => self.string(s),
+            LoxObject::List(values) => {
+                self.enter(values.as_ptr() as *const ())?;
+                let values = values.borrow();
+                self.out.push('[');
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.newline(depth + 1);
+                    self.value(v, depth + 1)?;
+                }
+                if !values.is_empty() {
+                    self.newline(depth);
+                }
+                self.out.push(']');
+                self.stack.pop();
+            }
+            LoxObject::Map(entries) => {
+                self.enter(entries.as_ptr() as *const ())?;
+                let entries = entries.borrow();
+                self.out.push('{');
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.newline(depth + 1);
+                    self.string(k);
+                    self.out.push(':');
+                    if self.indent > 0 {
+                        self.out.push(' ');
+                    }
+                    self.value(v, depth + 1)?;
+                }
+                if !entries.is_empty() {
+                    self.newline(depth);
+                }
+                self.out.push('}');
+                self.stack.pop();
+            }
+            LoxObject::Exit(v) => self.value(v, depth)?,
+            other => {
+                return Err(Self::error(format!(
+                    "cannot serialize a value of type {}",
+                    other.type_name()
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn string(&mut self, s: &str) {
+        self.out.push('"');
+        for ch in s.chars() {
+            match ch {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                '\u{8}' => self.out.push_str("\\b"),
+                '\u{c}' => self.out.push_str("\\f"),
+                ch if (ch as u32) < 0x20 => {
+                    let _ = write!(self.out, "\\u{:04x}", ch as u32);
+                }
+                ch => self.out.push(ch),
+            }
+        }
+        self.out.push('"');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::native::Clock;
+    use crate::testing::s;
+    use std::rc::Rc;
+
+    fn parse(src: &str) -> NativeResult {
+        json_parse(&mut LoxVisitor::new(), &[s(src)])
+    }
+
+    fn stringify(v: LoxObject, indent: f64) -> NativeResult {
+        json_stringify(&mut LoxVisitor::new(), &[v, LoxObject::Number(indent)])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let src = r#"{"b": [1, 2.5, -3e2], "a": {"x": null, "y": true}, "s": "q\"é\n"}"#;
+        let value = parse(src).unwrap();
+        assert_eq!(
+            stringify(value.clone(), 0.0),
+            Ok(s(
+                r#"{"a":{"x":null,"y":true},"b":[1,2.5,-300],"s":"q\"é\n"}"#
+            ))
+        );
+        let again = stringify(value.clone(), 2.0).unwrap();
+        assert_eq!(parse(&again.to_string()), Ok(value));
+    }
+
+    #[test]
+    fn test_indent() {
+        let value = parse(r#"{"a": [1], "b": {}}"#).unwrap();
+        assert_eq!(
+            stringify(value, 2.0),
+            Ok(s("{\n  \"a\": [\n    1\n  ],\n  \"b\": {}\n}"))
+        );
+    }
+
+    #[test]
+    fn test_surrogate_pair() {
+        assert_eq!(parse(r#""\uD83D\uDE00""#), Ok(s("😀")));
+        assert_eq!(parse(r#""\u00e9\u0041""#), Ok(s("éA")));
+        // a high surrogate needs a low one right after it, and vice versa.
+        for lone in [r#""\uD83D""#, r#""\uD83Dx""#, r#""\uD83DA""#, r#""\uDE00""#] {
+            assert!(parse(lone).is_err(), "{}", lone);
+        }
+    }
+
+    #[test]
+    fn test_number_grammar() {
+        assert_eq!(parse("0"), Ok(LoxObject::Integer(0)));
+        assert_eq!(parse("-0.5e+2"), Ok(LoxObject::Number(-50.0)));
+        assert_eq!(parse("10E-1"), Ok(LoxObject::Number(1.0)));
+        for invalid in [
+            "01", "-01", "1.", "1.e3", ".5", "-", "1e", "1e+", "+1", "--1",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(&nested(MAX_DEPTH + 1)),
+            Err(RuntimeError::Native(format!(
+                "\"json_parse\" nesting is deeper than {} levels at line 1, column {}",
+                MAX_DEPTH,
+                MAX_DEPTH + 1
+            )))
+        );
+        // far past the limit fails the same way rather than overflowing the stack.
+        assert!(parse(&"[".repeat(200_000)).is_err());
+        assert!(parse(&"{\"a\":".repeat(200_000)).is_err());
+    }
+
+    #[test]
+    fn test_malformed_input_reports_coordinates() {
+        assert_eq!(
+            parse("{\n  \"a\": tru\n}"),
+            Err(RuntimeError::Native(
+                "\"json_parse\" invalid literal, expected \"true\" at line 2, column 11"
+                    .to_string()
+            ))
+        );
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("[1] 2").is_err());
+        assert!(parse("\"abc").is_err());
+    }
+
+    #[test]
+    fn test_refuses_functions_and_cycles() {
+        let clock = LoxObject::Function(Rc::new(Clock));
+        assert!(stringify(clock, 0.0).is_err());
+
+        let list = LoxObject::list(vec![]);
+        if let LoxObject::List(ref values) = list {
+            values.borrow_mut().push(list.clone());
+        }
+        assert_eq!(
+            stringify(list.clone(), 0.0),
+            Err(RuntimeError::Native(
+                "\"json_stringify\" cannot serialize a cyclic structure".to_string()
+            ))
+        );
+        // break the cycle so the list is freed.
+        if let LoxObject::List(ref values) = list {
+            values.borrow_mut().clear();
+        }
+
+        // the same list appearing twice without a cycle is fine.
+        let shared = LoxObject::list(vec![LoxObject::Number(1.0)]);
+        let twice = LoxObject::list(vec![shared.clone(), shared]);
+        assert_eq!(stringify(twice, 0.0), Ok(s("[[1],[1]]")));
+    }
+}
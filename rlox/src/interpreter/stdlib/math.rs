@@ -0,0 +1,198 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::LoxObject;
+use crate::interpreter::stdlib::{define_native, expect_number, invalid_argument};
+use crate::interpreter::visitor::LoxVisitor;
+use std::cmp::Ordering;
+use std::f64::consts;
+
+type NativeResult = Result<LoxObject, RuntimeError>;
+
+pub fn register(env: &mut Environment) {
+    env.define_global("PI", LoxObject::Number(consts::PI));
+    env.define_global("E", LoxObject::Number(consts::E));
+    env.define_global("INF", LoxObject::Number(f64::INFINITY));
+    env.define_global("NAN", LoxObject::Number(f64::NAN));
+
+    define_native(env, "floor", 1, floor);
+    define_native(env, "ceil", 1, ceil);
+    define_native(env, "round", 1, round);
+    define_native(env, "abs", 1, abs);
+    define_native(env, "sqrt", 1, sqrt);
+    define_native(env, "pow", 2, pow);
+    define_native(env, "min", 2, min);
+    define_native(env, "max", 2, max);
+    define_native(env, "sin", 1, sin);
+    define_native(env, "cos", 1, cos);
+    define_native(env, "tan", 1, tan);
+    define_native(env, "atan2", 2, atan2);
+    define_native(env, "log", 1, log);
+    define_native(env, "exp", 1, exp);
+    define_native(env, "is_nan", 1, is_nan);
+    define_native(env, "is_finite", 1, is_finite);
+}
+
+fn unary(name: &'static str, args: &[LoxObject], f: fn(f64) -> f64) -> NativeResult {
+    Ok(LoxObject::Number(f(expect_number(name, args, 0)?)))
+}
+
+fn binary(name: &'static str, args: &[LoxObject], f: fn(f64, f64) -> f64) -> NativeResult {
+    let a = expect_number(name, args, 0)?;
+    let b = expect_number(name, args, 1)?;
+    Ok(LoxObject::Number(f(a, b)))
+}
+
+// floor, ceil and round give back an integer whenever the result fits in one.
+fn rounding(name: &'static str, args: &[LoxObject], f: fn(f64) -> f64) -> NativeResult {
+    if let LoxObject::Integer(i) = args[0] {
+        return Ok(LoxObject::Integer(i));
+    }
+    let n = f(expect_number(name, args, 0)?);
+    if n.is_finite() && n.abs() < i64::MAX as f64 {
+        Ok(LoxObject::Integer(n as i64))
+    } else {
+        Ok(LoxObject::Number(n))
+    }
+}
+
+fn floor(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    rounding("floor", args, f64::floor)
+}
+
+fn ceil(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    rounding("ceil", args, f64::ceil)
+}
+
+fn round(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    rounding("round", args, f64::round)
+}
+
+fn abs(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    match args[0] {
+        LoxObject::Integer(i) => i
+            .checked_abs()
+            .map(LoxObject::Integer)
+            .ok_or_else(|| invalid_argument("abs", 0, "number", &args[0])),
+        _ => unary("abs", args, f64::abs),
+    }
+}
+
+// sqrt and log reject inputs outside of their domain rather than producing NaN.
+fn sqrt(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    if expect_number("sqrt", args, 0)? < 0.0 {
+        return Err(invalid_argument("sqrt", 0, "non-negative number", &args[0]));
+    }
+    unary("sqrt", args, f64::sqrt)
+}
+
+fn log(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    if expect_number("log", args, 0)? < 0.0 {
+        return Err(invalid_argument("log", 0, "non-negative number", &args[0]));
+    }
+    unary("log", args, f64::ln)
+}
+
+fn pow(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    binary("pow", args, f64::powf)
+}
+
+// min and max return one of their arguments, so integers stay integers.
+fn pick(name: &'static str, args: &[LoxObject], first: fn(Ordering) -> bool) -> NativeResult {
+    let a = expect_number(name, args, 0)?;
+    let b = expect_number(name, args, 1)?;
+    let ordering = match (&args[0], &args[1]) {
+        (LoxObject::Integer(x), LoxObject::Integer(y)) => x.cmp(y),
+        _ => match a.partial_cmp(&b) {
+            Some(ordering) => ordering,
+            None => return Ok(LoxObject::Number(f64::NAN)),
+        },
+    };
+    Ok(if first(ordering) { &args[0] } else { &args[1] }.clone())
+}
+
+fn min(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    pick("min", args, Ordering::is_le)
+}
+
+fn max(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    pick("max", args, Ordering::is_ge)
+}
+
+fn sin(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    unary("sin", args, f64::sin)
+}
+
+fn cos(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    unary("cos", args, f64::cos)
+}
+
+fn tan(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    unary("tan", args, f64::tan)
+}
+
+fn atan2(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    binary("atan2", args, f64::atan2)
+}
+
+fn exp(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    unary("exp", args, f64::exp)
+}
+
+fn is_nan(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    Ok(LoxObject::Boolean(
+        expect_number("is_nan", args, 0)?.is_nan(),
+    ))
+}
+
+fn is_finite(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    Ok(LoxObject::Boolean(
+        expect_number("is_finite", args, 0)?.is_finite(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{call, n};
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(call(floor, &[n(1.7)]), Ok(n(1.0)));
+        assert_eq!(call(ceil, &[n(1.2)]), Ok(n(2.0)));
+        assert_eq!(call(round, &[n(-1.5)]), Ok(n(-2.0)));
+        assert_eq!(call(abs, &[n(-3.0)]), Ok(n(3.0)));
+    }
+
+    #[test]
+    fn test_binary_functions() {
+        assert_eq!(call(pow, &[n(2.0), n(10.0)]), Ok(n(1024.0)));
+        assert_eq!(call(min, &[n(2.0), n(-1.0)]), Ok(n(-1.0)));
+        assert_eq!(call(max, &[n(2.0), n(-1.0)]), Ok(n(2.0)));
+        assert_eq!(call(atan2, &[n(0.0), n(1.0)]), Ok(n(0.0)));
+    }
+
+    #[test]
+    fn test_nan_checks() {
+        assert_eq!(call(is_nan, &[n(f64::NAN)]), Ok(LoxObject::Boolean(true)));
+        assert_eq!(
+            call(is_finite, &[n(f64::INFINITY)]),
+            Ok(LoxObject::Boolean(false))
+        );
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert_eq!(
+            call(sqrt, &[LoxObject::Nil]),
+            Err(RuntimeError::InvalidArgument(
+                "sqrt",
+                1,
+                "number",
+                LoxObject::Nil
+            ))
+        );
+        assert!(call(sqrt, &[n(-1.0)]).is_err());
+        assert!(call(log, &[n(-1.0)]).is_err());
+        assert!(call(pow, &[n(1.0), LoxObject::String("2".to_string())]).is_err());
+    }
+}
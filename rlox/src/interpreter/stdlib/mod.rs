@@ -0,0 +1,89 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::native::{NativeFn, NativeFunction};
+use crate::interpreter::primitive::LoxObject;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub mod collections;
+pub mod fiber;
+pub mod io;
+pub mod json;
+pub mod math;
+pub mod process;
+pub mod random;
+pub mod string;
+
+// registers every native module into the given (global) environment.
+pub fn load(env: &mut Environment) {
+    string::register(env);
+    math::register(env);
+    random::register(env);
+    io::register(env);
+    process::register(env);
+    collections::register(env);
+    json::register(env);
+    fiber::register(env);
+}
+
+pub fn define_native(env: &mut Environment, name: &'static str, arity: usize, func: NativeFn) {
+    env.define_global(
+        name,
+        LoxObject::Function(Rc::new(NativeFunction::new(name, arity, func))),
+    );
+}
+
+// argument helpers, "position" is the zero based index into args but errors
+// report it one based as that is what a user would count.
+pub fn expect_string<'a>(
+    func: &'static str,
+    args: &'a [LoxObject],
+    position: usize,
+) -> Result<&'a str, RuntimeError> {
+    match &args[position] {
+        LoxObject::String(s) => Ok(s),
+        other => Err(invalid_argument(func, position, "string", other)),
+    }
+}
+
+pub fn expect_number(
+    func: &'static str,
+    args: &[LoxObject],
+    position: usize,
+) -> Result<f64, RuntimeError> {
+    args[position]
+        .as_number()
+        .ok_or_else(|| invalid_argument(func, position, "number", &args[position]))
+}
+
+pub fn expect_integer(
+    func: &'static str,
+    args: &[LoxObject],
+    position: usize,
+) -> Result<i64, RuntimeError> {
+    match &args[position] {
+        LoxObject::Integer(i) => Ok(*i),
+        LoxObject::Number(n) if n.fract() == 0.0 => Ok(*n as i64),
+        other => Err(invalid_argument(func, position, "integer", other)),
+    }
+}
+
+pub fn expect_list<'a>(
+    func: &'static str,
+    args: &'a [LoxObject],
+    position: usize,
+) -> Result<&'a Rc<RefCell<Vec<LoxObject>>>, RuntimeError> {
+    match &args[position] {
+        LoxObject::List(values) => Ok(values),
+        other => Err(invalid_argument(func, position, "list", other)),
+    }
+}
+
+pub fn invalid_argument(
+    func: &'static str,
+    position: usize,
+    expected: &'static str,
+    found: &LoxObject,
+) -> RuntimeError {
+    RuntimeError::InvalidArgument(func, position + 1, expected, found.clone())
+}
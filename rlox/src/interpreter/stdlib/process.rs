@@ -0,0 +1,82 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::LoxObject;
+use crate::interpreter::stdlib::{define_native, expect_integer, expect_string, invalid_argument};
+use crate::interpreter::visitor::LoxVisitor;
+
+type NativeResult = Result<LoxObject, RuntimeError>;
+
+pub fn register(env: &mut Environment) {
+    define_native(env, "args", 0, args);
+    define_native(env, "env", 1, env_var);
+    define_native(env, "exit", 1, exit);
+}
+
+// the arguments following the script path on the command line.
+fn args(interpreter: &mut LoxVisitor, _: &[LoxObject]) -> NativeResult {
+    let values = interpreter
+        .script_args()
+        .iter()
+        .map(|arg| LoxObject::String(arg.clone()))
+        .collect();
+    Ok(LoxObject::list(values))
+}
+
+// env(name) returns nil when the variable is unset (or isn't valid unicode).
+fn env_var(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    if !interpreter.capabilities().env {
+        return Err(RuntimeError::CapabilityDisabled("env", "env"));
+    }
+    let name = expect_string("env", args, 0)?;
+    Ok(std::env::var(name)
+        .map(LoxObject::String)
+        .unwrap_or(LoxObject::Nil))
+}
+
+// exit doesn't terminate the process itself, it unwinds the interpreter with
+// an exit "error" and leaves it to the embedder (our cli) to actually exit.
+fn exit(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let code = expect_integer("exit", args, 0)?;
+    let code =
+        i32::try_from(code).map_err(|_| invalid_argument("exit", 0, "exit code", &args[0]))?;
+    Err(RuntimeError::Exit(code))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::visitor::Capabilities;
+    use crate::testing::run;
+
+    #[test]
+    fn test_args() {
+        let mut interpreter = LoxVisitor::new();
+        interpreter.set_script_args(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(
+            args(&mut interpreter, &[]),
+            Ok(LoxObject::list(vec![
+                LoxObject::String("a".to_string()),
+                LoxObject::String("b".to_string())
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_env() {
+        let mut interpreter = LoxVisitor::new();
+        let unset = LoxObject::String("RLOX_SURELY_UNSET_VARIABLE".to_string());
+        assert_eq!(
+            env_var(&mut interpreter, std::slice::from_ref(&unset)),
+            Ok(LoxObject::Nil)
+        );
+
+        let mut sandboxed = LoxVisitor::with_capabilities(Capabilities::none());
+        assert!(env_var(&mut sandboxed, &[unset]).is_err());
+    }
+
+    #[test]
+    fn test_exit_unwinds_interpreter() {
+        let (_, result) = run("var a = 1; fun f() { exit(3); a = 2; } f(); a = 3;");
+        assert_eq!(result, Err(RuntimeError::Exit(3)));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::LoxObject;
+use crate::interpreter::stdlib::{define_native, expect_integer, expect_list};
+use crate::interpreter::visitor::LoxVisitor;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type NativeResult = Result<LoxObject, RuntimeError>;
+
+// xorshift64* seeded through splitmix64, small and fast and more than good
+// enough for scripting. Each interpreter owns its own generator so that two
+// interpreters seeded with the same value produce the same sequence.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 so that small or similar seeds still give a well mixed,
+        // non zero state (xorshift gets stuck on zero).
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self {
+            state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z },
+        }
+    }
+
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // uniform float in [0, 1) built from the top 53 bits.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniform integer in [0, bound), bound must be non zero.
+    pub fn next_below(&mut self, bound: u64) -> u64 {
+        // rejection sampling to avoid modulo bias.
+        let zone = u64::MAX - (u64::MAX % bound);
+        loop {
+            let v = self.next_u64();
+            if v < zone {
+                return v % bound;
+            }
+        }
+    }
+}
+
+pub fn register(env: &mut Environment) {
+    define_native(env, "random", 0, random);
+    define_native(env, "random_int", 2, random_int);
+    define_native(env, "shuffle", 1, shuffle);
+    define_native(env, "choice", 1, choice);
+    define_native(env, "seed", 1, seed);
+}
+
+fn random(interpreter: &mut LoxVisitor, _: &[LoxObject]) -> NativeResult {
+    Ok(LoxObject::Number(interpreter.rng().next_f64()))
+}
+
+// random_int(lo, hi), both bounds are inclusive.
+fn random_int(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let lo = expect_integer("random_int", args, 0)?;
+    let hi = expect_integer("random_int", args, 1)?;
+    if lo > hi {
+        return Err(RuntimeError::Native(format!(
+            "\"random_int\" lower bound {} is greater than upper bound {}",
+            lo, hi
+        )));
+    }
+    let span = hi.abs_diff(lo).wrapping_add(1);
+    let offset = if span == 0 {
+        // the full 64 bit range.
+        interpreter.rng().next_u64()
+    } else {
+        interpreter.rng().next_below(span)
+    };
+    Ok(LoxObject::Integer(lo.wrapping_add(offset as i64)))
+}
+
+// shuffles the list in place (fisher-yates) and returns it.
+fn shuffle(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let list = expect_list("shuffle", args, 0)?;
+    let mut values = list.borrow_mut();
+    for i in (1..values.len()).rev() {
+        let j = interpreter.rng().next_below(i as u64 + 1) as usize;
+        values.swap(i, j);
+    }
+    Ok(args[0].clone())
+}
+
+fn choice(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let values = expect_list("choice", args, 0)?.borrow();
+    if values.is_empty() {
+        return Err(RuntimeError::Native(
+            "\"choice\" cannot choose from an empty list".to_string(),
+        ));
+    }
+    let i = interpreter.rng().next_below(values.len() as u64) as usize;
+    Ok(values[i].clone())
+}
+
+fn seed(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let n = expect_integer("seed", args, 0)?;
+    *interpreter.rng() = Rng::new(n as u64);
+    Ok(LoxObject::Nil)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::n;
+
+    fn sample(interpreter: &mut LoxVisitor) -> Vec<LoxObject> {
+        let list = LoxObject::list((0..10).map(|i| n(i as f64)).collect());
+        vec![
+            random(interpreter, &[]).unwrap(),
+            random_int(interpreter, &[n(-5.0), n(5.0)]).unwrap(),
+            shuffle(interpreter, std::slice::from_ref(&list)).unwrap(),
+            choice(interpreter, &[list]).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = LoxVisitor::new();
+        let mut b = LoxVisitor::new();
+        seed(&mut a, &[n(42.0)]).unwrap();
+        seed(&mut b, &[n(42.0)]).unwrap();
+        assert_eq!(sample(&mut a), sample(&mut b));
+
+        seed(&mut b, &[n(43.0)]).unwrap();
+        assert_ne!(sample(&mut a), sample(&mut b));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut interpreter = LoxVisitor::new();
+        for _ in 0..1000 {
+            match random(&mut interpreter, &[]).unwrap() {
+                LoxObject::Number(v) => assert!((0.0..1.0).contains(&v)),
+                other => panic!("expected a number, got {}", other),
+            }
+            match random_int(&mut interpreter, &[n(1.0), n(3.0)]).unwrap() {
+                LoxObject::Integer(v) => assert!([1, 2, 3].contains(&v)),
+                other => panic!("expected a number, got {}", other),
+            }
+        }
+        assert!(random_int(&mut interpreter, &[n(3.0), n(1.0)]).is_err());
+        assert!(choice(&mut interpreter, &[LoxObject::list(vec![])]).is_err());
+    }
+
+    #[test]
+    fn test_shuffle_is_a_permutation() {
+        let mut interpreter = LoxVisitor::new();
+        let list = LoxObject::list((0..20).map(|i| n(i as f64)).collect());
+        shuffle(&mut interpreter, std::slice::from_ref(&list)).unwrap();
+
+        if let LoxObject::List(values) = list {
+            let mut sorted: Vec<f64> = values
+                .borrow()
+                .iter()
+                .map(|v| match v {
+                    LoxObject::Number(n) => *n,
+                    _ => unreachable!(),
+                })
+                .collect();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(sorted, (0..20).map(|i| i as f64).collect::<Vec<f64>>());
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use crate::interpreter::environment::Environment;
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::LoxObject;
+use crate::interpreter::stdlib::{
+    define_native, expect_integer, expect_list, expect_string, invalid_argument,
+};
+use crate::interpreter::visitor::LoxVisitor;
+
+type NativeResult = Result<LoxObject, RuntimeError>;
+
+// the longest string repeat will build, in bytes.
+const MAX_STRING_LEN: usize = 1 << 28;
+
+pub fn register(env: &mut Environment) {
+    define_native(env, "len", 1, len);
+    define_native(env, "substr", 3, substr);
+    define_native(env, "slice", 3, slice);
+    define_native(env, "index_of", 2, index_of);
+    define_native(env, "contains", 2, contains);
+    define_native(env, "starts_with", 2, starts_with);
+    define_native(env, "ends_with", 2, ends_with);
+    define_native(env, "split", 2, split);
+    define_native(env, "join", 2, join);
+    define_native(env, "trim", 1, trim);
+    define_native(env, "upper", 1, upper);
+    define_native(env, "lower", 1, lower);
+    define_native(env, "replace", 3, replace);
+    define_native(env, "repeat", 2, repeat);
+    define_native(env, "char_at", 2, char_at);
+    define_native(env, "ord", 1, ord);
+    define_native(env, "chr", 1, chr);
+    define_native(env, "str", 1, str);
+    define_native(env, "num", 1, num);
+}
+
+// all indices are character (not byte) offsets so that non ascii text behaves.
+fn char_count(s: &str) -> usize {
+    s.chars().count()
+}
+
+fn char_slice(s: &str, start: usize, end: usize) -> String {
+    s.chars()
+        .skip(start)
+        .take(end.saturating_sub(start))
+        .collect()
+}
+
+// resolves a possibly negative index against a length, clamping to [0, len].
+fn clamp_index(i: i64, len: usize) -> usize {
+    if i < 0 {
+        (len as i64 + i).max(0) as usize
+    } else {
+        (i as usize).min(len)
+    }
+}
+
+fn len(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    match &args[0] {
+        LoxObject::String(s) => Ok(LoxObject::Integer(char_count(s) as i64)),
+        LoxObject::List(values) => Ok(LoxObject::Integer(values.borrow().len() as i64)),
+        LoxObject::Map(entries) => Ok(LoxObject::Integer(entries.borrow().len() as i64)),
+        other => Err(invalid_argument("len", 0, "string, list or map", other)),
+    }
+}
+
+// substr(s, start, length)
+fn substr(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("substr", args, 0)?;
+    let len = char_count(s);
+    let start = clamp_index(expect_integer("substr", args, 1)?, len);
+    let length = expect_integer("substr", args, 2)?;
+    if length < 0 {
+        return Err(invalid_argument(
+            "substr",
+            2,
+            "non-negative length",
+            &args[2],
+        ));
+    }
+    let end = start.saturating_add(length as usize).min(len);
+    Ok(LoxObject::String(char_slice(s, start, end)))
+}
+
+// slice(s, start, end), negative indices count back from the end.
+fn slice(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("slice", args, 0)?;
+    let len = char_count(s);
+    let start = clamp_index(expect_integer("slice", args, 1)?, len);
+    let end = clamp_index(expect_integer("slice", args, 2)?, len);
+    Ok(LoxObject::String(char_slice(s, start, end)))
+}
+
+fn index_of(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("index_of", args, 0)?;
+    let needle = expect_string("index_of", args, 1)?;
+    let index = s
+        .find(needle)
+        .map(|byte_index| char_count(&s[..byte_index]) as i64)
+        .unwrap_or(-1);
+    Ok(LoxObject::Integer(index))
+}
+
+fn contains(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("contains", args, 0)?;
+    let needle = expect_string("contains", args, 1)?;
+    Ok(LoxObject::Boolean(s.contains(needle)))
+}
+
+fn starts_with(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("starts_with", args, 0)?;
+    let prefix = expect_string("starts_with", args, 1)?;
+    Ok(LoxObject::Boolean(s.starts_with(prefix)))
+}
+
+fn ends_with(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("ends_with", args, 0)?;
+    let suffix = expect_string("ends_with", args, 1)?;
+    Ok(LoxObject::Boolean(s.ends_with(suffix)))
+}
+
+// split(s, sep), an empty separator splits into individual characters.
+fn split(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("split", args, 0)?;
+    let sep = expect_string("split", args, 1)?;
+    let parts = if sep.is_empty() {
+        s.chars()
+            .map(|c| LoxObject::String(c.to_string()))
+            .collect()
+    } else {
+        s.split(sep)
+            .map(|part| LoxObject::String(part.to_string()))
+            .collect()
+    };
+    Ok(LoxObject::list(parts))
+}
+
+fn join(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let values = expect_list("join", args, 0)?;
+    let sep = expect_string("join", args, 1)?;
+    let joined = values
+        .borrow()
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(sep);
+    Ok(LoxObject::String(joined))
+}
+
+fn trim(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("trim", args, 0)?;
+    Ok(LoxObject::String(s.trim().to_string()))
+}
+
+fn upper(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("upper", args, 0)?;
+    Ok(LoxObject::String(s.to_uppercase()))
+}
+
+fn lower(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("lower", args, 0)?;
+    Ok(LoxObject::String(s.to_lowercase()))
+}
+
+fn replace(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("replace", args, 0)?;
+    let from = expect_string("replace", args, 1)?;
+    let to = expect_string("replace", args, 2)?;
+    if from.is_empty() {
+        return Err(invalid_argument("replace", 1, "non-empty string", &args[1]));
+    }
+    Ok(LoxObject::String(s.replace(from, to)))
+}
+
+fn repeat(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("repeat", args, 0)?;
+    let n = expect_integer("repeat", args, 1)?;
+    if n < 0 {
+        return Err(invalid_argument(
+            "repeat",
+            1,
+            "non-negative integer",
+            &args[1],
+        ));
+    }
+    match s.len().checked_mul(n as usize) {
+        Some(total) if total <= MAX_STRING_LEN => Ok(LoxObject::String(s.repeat(n as usize))),
+        _ => Err(RuntimeError::Native(format!(
+            "\"repeat\" result would be longer than {} bytes",
+            MAX_STRING_LEN
+        ))),
+    }
+}
+
+fn char_at(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("char_at", args, 0)?;
+    let i = expect_integer("char_at", args, 1)?;
+    let ch = if i < 0 {
+        None
+    } else {
+        s.chars().nth(i as usize)
+    };
+    ch.map(|c| LoxObject::String(c.to_string())).ok_or_else(|| {
+        RuntimeError::Native(format!(
+            "\"char_at\" index {} out of range for string of length {}",
+            i,
+            char_count(s)
+        ))
+    })
+}
+
+fn ord(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = expect_string("ord", args, 0)?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(LoxObject::Integer(c as i64)),
+        _ => Err(invalid_argument(
+            "ord",
+            0,
+            "single character string",
+            &args[0],
+        )),
+    }
+}
+
+fn chr(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let code = expect_integer("chr", args, 0)?;
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .map(|c| LoxObject::String(c.to_string()))
+        .ok_or_else(|| invalid_argument("chr", 0, "valid unicode code point", &args[0]))
+}
+
+fn str(interpreter: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    Ok(LoxObject::String(interpreter.display(&args[0])?))
+}
+
+// num(s) returns nil when the string isn't a plain decimal number, integers
+// are parsed as such ("3" gives 3, while "3.0" gives a float).
+fn num(_: &mut LoxVisitor, args: &[LoxObject]) -> NativeResult {
+    let s = match &args[0] {
+        n @ (LoxObject::Integer(_) | LoxObject::Number(_)) => return Ok(n.clone()),
+        LoxObject::String(s) => s.trim(),
+        other => return Err(invalid_argument("num", 0, "string or number", other)),
+    };
+
+    let is_numeric = s
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'));
+
+    if let Ok(i) = s.parse::<i64>() {
+        return Ok(LoxObject::Integer(i));
+    }
+    match s.parse::<f64>() {
+        Ok(n) if is_numeric => Ok(LoxObject::Number(n)),
+        _ => Ok(LoxObject::Nil),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{call, n, s};
+
+    #[test]
+    fn test_character_indexing() {
+        assert_eq!(call(len, &[s("héllo")]).unwrap(), n(5.0));
+        assert_eq!(
+            call(substr, &[s("héllo"), n(1.0), n(3.0)]).unwrap(),
+            s("éll")
+        );
+        assert_eq!(
+            call(slice, &[s("héllo"), n(1.0), n(-1.0)]).unwrap(),
+            s("éll")
+        );
+        assert_eq!(call(index_of, &[s("héllo"), s("l")]).unwrap(), n(2.0));
+        assert_eq!(call(index_of, &[s("héllo"), s("z")]).unwrap(), n(-1.0));
+        assert_eq!(call(char_at, &[s("héllo"), n(1.0)]).unwrap(), s("é"));
+        assert!(call(char_at, &[s("abc"), n(3.0)]).is_err());
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let parts = call(split, &[s("a,b,,c"), s(",")]).unwrap();
+        assert_eq!(parts, LoxObject::list(vec![s("a"), s("b"), s(""), s("c")]));
+        assert_eq!(call(join, &[parts, s("-")]).unwrap(), s("a-b--c"));
+        assert_eq!(
+            call(split, &[s("ab"), s("")]).unwrap(),
+            LoxObject::list(vec![s("a"), s("b")])
+        );
+    }
+
+    #[test]
+    fn test_formatting() {
+        assert_eq!(call(trim, &[s("  hi \n")]).unwrap(), s("hi"));
+        assert_eq!(call(upper, &[s("hi")]).unwrap(), s("HI"));
+        assert_eq!(call(lower, &[s("HI")]).unwrap(), s("hi"));
+        assert_eq!(
+            call(replace, &[s("a-b-c"), s("-"), s("+")]).unwrap(),
+            s("a+b+c")
+        );
+        assert_eq!(call(repeat, &[s("ab"), n(3.0)]).unwrap(), s("ababab"));
+        assert_eq!(call(repeat, &[s(""), n(1e15)]).unwrap(), s(""));
+        for count in [1e11, i64::MAX as f64] {
+            assert_eq!(
+                repeat(&mut LoxVisitor::new(), &[s("ab"), n(count)]),
+                Err(RuntimeError::Native(
+                    "\"repeat\" result would be longer than 268435456 bytes".to_string()
+                ))
+            );
+        }
+        assert_eq!(call(ord, &[s("A")]).unwrap(), n(65.0));
+        assert_eq!(call(chr, &[n(65.0)]).unwrap(), s("A"));
+    }
+
+    #[test]
+    fn test_number_conversion() {
+        assert_eq!(call(str, &[n(3.5)]).unwrap(), s("3.5"));
+        assert_eq!(call(num, &[s(" 3.5 ")]).unwrap(), n(3.5));
+        assert_eq!(call(num, &[s("-1e3")]).unwrap(), n(-1000.0));
+        assert_eq!(call(num, &[s("abc")]).unwrap(), LoxObject::Nil);
+        assert_eq!(call(num, &[s("inf")]).unwrap(), LoxObject::Nil);
+        assert!(call(num, &[LoxObject::Nil]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_argument_type() {
+        let err = call(upper, &[n(1.0)]).unwrap_err();
+        assert_eq!(
+            err,
+            RuntimeError::InvalidArgument("upper", 1, "string", n(1.0))
+        );
+    }
+}
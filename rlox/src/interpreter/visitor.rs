@@ -1,43 +1,181 @@
-use crate::interpreter::environment::Environment;
+use crate::interpreter::environment::{AssignError, Environment};
 use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::generator::LoxIterator;
+use crate::interpreter::module::{load_source, Modules};
 use crate::interpreter::native::{Clock, LoxFunction};
-use crate::interpreter::primitive::LoxObject;
-use crate::language::ast::{Expr, ExprVisitor, Stmt, StmtVisitor};
-use crate::language::token::{Token, TokenType};
+use crate::interpreter::primitive::{Callable, LoxObject};
+use crate::interpreter::stdlib;
+use crate::interpreter::stdlib::fiber::{self, Scheduler};
+use crate::interpreter::stdlib::random::Rng;
+use crate::language::ast::{
+    map_key, Expr, ExprVisitor, MatchArm, Param, Pattern, Stmt, StmtVisitor, TypeAnnotation,
+};
+use crate::language::token::{Literal, Slot, Token, TokenType};
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::rc::Rc;
 
 type InterpreterResult = Result<LoxObject, RuntimeError>;
 
+// what an embedding allows scripts to touch outside of the interpreter,
+// natives that need a disabled capability fail with a runtime error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub filesystem: bool,
+    pub stdin: bool,
+    pub env: bool,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Self {
+            filesystem: true,
+            stdin: true,
+            env: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            filesystem: false,
+            stdin: false,
+            env: false,
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
 // this will eventually have state;
 pub struct LoxVisitor {
     globals: Rc<RefCell<Environment>>,
     environment: Rc<RefCell<Environment>>,
+    rng: Rng,
+    capabilities: Capabilities,
+    script_args: Vec<String>,
+    modules: Modules,
+    scheduler: Scheduler,
 }
 
 impl LoxVisitor {
     pub fn new() -> LoxVisitor {
+        Self::with_capabilities(Capabilities::default())
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities) -> LoxVisitor {
         let globals = Self::get_global_env();
-        let environment = Environment::new_rc(Some(globals.clone()));
+        let environment = Environment::new_module(Some(globals.clone()));
 
         LoxVisitor {
             globals,
             environment,
+            rng: Rng::from_time(),
+            capabilities,
+            script_args: Vec::new(),
+            modules: Modules::new(),
+            scheduler: Scheduler::default(),
+        }
+    }
+
+    pub fn set_script_args(&mut self, args: Vec<String>) {
+        self.script_args = args;
+    }
+
+    // the path of the script being run, relative imports resolve next to it.
+    pub fn set_script_path(&mut self, path: &Path) {
+        self.modules.set_root(path);
+    }
+
+    pub fn script_args(&self) -> &[String] {
+        &self.script_args
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    // runs the module at "path" (once) and returns it as a module object,
+    // which reads the module's top level definitions as they are when used
+    // and can't assign them. "from ... import" copies the values they have at
+    // the time of the import instead.
+    fn import_module(&mut self, keyword: &Token, path: &str) -> InterpreterResult {
+        let failed =
+            |reason: String| RuntimeError::ImportFailed(keyword.clone(), path.into(), reason);
+
+        if !self.capabilities.filesystem {
+            return Err(RuntimeError::CapabilityDisabled("import", "filesystem"));
+        }
+
+        let importer = Environment::module_path(&self.environment);
+        let resolved = self
+            .modules
+            .resolve(path, importer.as_deref())
+            .map_err(failed)?;
+        if let Some(module) = self.modules.cached(&resolved) {
+            return Ok(module);
+        }
+
+        self.modules.begin(resolved.clone()).map_err(failed)?;
+        let result = load_source(&resolved).map_err(failed).and_then(|stmts| {
+            let env = Environment::new_module_at(Some(self.globals.clone()), &resolved);
+            match self.execute_block(env.clone(), &stmts) {
+                Ok(_) => Ok(env),
+                Err(e) if !e.is_catchable() => Err(e),
+                Err(e) => Err(failed(e.to_string())),
+            }
+        });
+
+        match result {
+            Ok(env) => {
+                let module = LoxObject::Module(env);
+                self.modules.finish(Some(module.clone()));
+                Ok(module)
+            }
+            Err(e) => {
+                self.modules.finish(None);
+                Err(e)
+            }
         }
     }
 
+    fn declare(&mut self, name: &Token, value: LoxObject) -> Result<(), RuntimeError> {
+        self.environment
+            .borrow_mut()
+            .declare(name, value)
+            .map_err(|e| assign_error(e, name))
+    }
+
     fn get_global_env() -> Rc<RefCell<Environment>> {
         let mut env = Environment::new(None);
-        env.define("clock".to_string(), LoxObject::Function(Rc::new(Clock)));
-        // todo - add the rest of the native apis...
+        env.define_global("clock", LoxObject::Function(Rc::new(Clock)));
+        stdlib::load(&mut env);
         Rc::new(RefCell::new(env))
     }
 
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    pub fn scheduler(&mut self) -> &mut Scheduler {
+        &mut self.scheduler
+    }
+
+    pub fn define_global(&mut self, name: &str, value: LoxObject) {
+        self.globals.borrow_mut().define_global(name, value);
+    }
+
     pub fn interpret(&mut self, stmts: &[Stmt]) -> Result<(), RuntimeError> {
         for stmt in stmts {
             stmt.accept(self)?;
         }
-        Ok(())
+        // spawned fibers run once the script itself is done.
+        fiber::run(self)
     }
 
     pub fn create_new_environment(&mut self) -> Rc<RefCell<Environment>> {
@@ -48,6 +186,91 @@ impl LoxVisitor {
         self.environment = new_env;
     }
 
+    pub fn call_function(
+        &mut self,
+        f: &Rc<dyn Callable>,
+        args: &[LoxObject],
+        paren: &Token,
+    ) -> InterpreterResult {
+        let got = args.len();
+        if got < f.arity() || f.max_arity().is_some_and(|max| got > max) {
+            return Err(RuntimeError::ArityMismatch(
+                paren.clone(),
+                f.arity(),
+                f.max_arity(),
+                got,
+            ));
+        }
+        // natives don't know where they were called from, so errors
+        // without a location are pinned to the call site.
+        f.call(self, args).map_err(|e| {
+            if e.coordinate().is_none() && e.is_catchable() {
+                RuntimeError::AtCall(Box::new(e), paren.clone())
+            } else {
+                e
+            }
+        })
+    }
+
+    // how print and str() show a value, a map can define "__str" to choose.
+    pub fn display(&mut self, value: &LoxObject) -> Result<String, RuntimeError> {
+        match method(value, "__str") {
+            Some(f) => {
+                let shown = f.call(self, std::slice::from_ref(value))?;
+                Ok(shown.to_string())
+            }
+            None => Ok(value.to_string()),
+        }
+    }
+
+    // maps can stand in for instances and define what an operator does when
+    // either operand is one, e.g. "__add" is called with both operands in
+    // source order. ">" and ">=" call "__lt" and "__le" with them swapped
+    // and "!=" negates "__eq".
+    fn apply_overload(
+        &mut self,
+        left: &LoxObject,
+        operator: &Token,
+        right: &LoxObject,
+    ) -> Result<Option<LoxObject>, RuntimeError> {
+        let (name, swap, negate) = match operator.token_type {
+            TokenType::Plus => ("__add", false, false),
+            TokenType::Minus => ("__sub", false, false),
+            TokenType::Star => ("__mul", false, false),
+            TokenType::Slash => ("__div", false, false),
+            TokenType::EqualEqual => ("__eq", false, false),
+            TokenType::BangEqual => ("__eq", false, true),
+            TokenType::Less => ("__lt", false, false),
+            TokenType::LessEqual => ("__le", false, false),
+            TokenType::Greater => ("__lt", true, false),
+            TokenType::GreaterEqual => ("__le", true, false),
+            _ => return Ok(None),
+        };
+        let (a, b) = if swap { (right, left) } else { (left, right) };
+        let Some(f) = method(a, name).or_else(|| method(b, name)) else {
+            return Ok(None);
+        };
+
+        let result = self.call_function(&f, &[a.clone(), b.clone()], operator)?;
+        if negate {
+            return Ok(Some(LoxObject::Boolean(!is_truthy(&result))));
+        }
+        Ok(Some(result))
+    }
+
+    // evaluates an expression as if it appeared in the given scope, used for
+    // default parameter values which see the callee's earlier parameters.
+    pub fn evaluate_in(
+        &mut self,
+        new_env: Rc<RefCell<Environment>>,
+        expr: &Expr,
+    ) -> InterpreterResult {
+        let origin = std::mem::replace(&mut self.environment, new_env);
+        let result = expr.accept(self);
+        self.environment = origin;
+        result
+    }
+
     pub fn execute_block(
         &mut self,
         new_env: Rc<RefCell<Environment>>,
@@ -58,10 +281,10 @@ impl LoxVisitor {
 
         for stmt in statements {
             match stmt.accept(self) {
-                // handle a potential break statement.
-                Ok(LoxObject::Exit(v)) => {
+                // handle a potential break or return statement.
+                Ok(exit @ (LoxObject::Exit(_) | LoxObject::Break)) => {
                     self.environment = origin; // Restore original environment
-                    return Ok(LoxObject::Exit(v));
+                    return Ok(exit);
                 }
                 Err(e) => {
                     self.environment = origin; // Restore original environment
@@ -76,52 +299,124 @@ impl LoxVisitor {
     }
 }
 
+impl Default for LoxVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ExprVisitor<InterpreterResult> for LoxVisitor {
     fn visit_binary(
         &mut self,
-        left: Box<Expr>,
-        operator: Token,
-        right: Box<Expr>,
+        left: &Box<Expr>,
+        operator: &Token,
+        right: &Box<Expr>,
     ) -> InterpreterResult {
         let left = left.accept(self)?;
         let right = right.accept(self)?;
+
+        if let Some(result) = self.apply_overload(&left, operator, &right)? {
+            return Ok(result);
+        }
+        if operator.token_type == TokenType::Plus && either_is_string(&left, &right) {
+            let (left, right) = (self.display(&left)?, self.display(&right)?);
+            return Ok(LoxObject::String(left + &right));
+        }
         apply_binary(left, operator, right)
     }
 
-    fn visit_grouping(&mut self, expression: Box<Expr>) -> InterpreterResult {
+    fn visit_grouping(&mut self, expression: &Box<Expr>) -> InterpreterResult {
         expression.accept(self)
     }
 
-    fn visit_literal(&mut self, literal: Token) -> InterpreterResult {
-        Ok(literal.literal.into())
+    fn visit_literal(&mut self, literal: &Token) -> InterpreterResult {
+        Ok(literal.literal.clone().into())
     }
 
-    fn visit_unary(&mut self, operator: Token, right: Box<Expr>) -> InterpreterResult {
+    fn visit_unary(&mut self, operator: &Token, right: &Box<Expr>) -> InterpreterResult {
         let right = right.accept(self)?;
         apply_unary(operator, right)
     }
 
-    fn visit_variable(&mut self, name: Token) -> InterpreterResult {
-        name.with_lexeme(|word| match self.environment.borrow_mut().get(word) {
-            Some(value) => Ok(value.clone()),
+    fn visit_variable(&mut self, name: &Token) -> InterpreterResult {
+        match self.environment.borrow().get(name) {
+            Some(value) => Ok(value),
             None => Err(RuntimeError::UndefinedVariable(name.clone())),
-        })
+        }
     }
 
-    fn visit_assign(&mut self, name: Token, value: Box<Expr>) -> InterpreterResult {
+    fn visit_assign(&mut self, name: &Token, value: &Box<Expr>) -> InterpreterResult {
         let v = value.accept(self)?;
         self.environment
             .borrow_mut()
-            .assign(name.lexeme.clone().unwrap(), v.clone())
-            .map_err(|_| RuntimeError::UndefinedVariable(name))?;
+            .assign(name, v.clone())
+            .map_err(|e| assign_error(e, name))?;
+        Ok(v)
+    }
+
+    fn visit_destructure_assign(
+        &mut self,
+        pattern: &Box<Pattern>,
+        equals: &Token,
+        value: &Box<Expr>,
+    ) -> InterpreterResult {
+        let v = value.accept(self)?;
+        for (name, bound) in destructure(pattern, &v, equals)? {
+            self.environment
+                .borrow_mut()
+                .assign(&name, bound)
+                .map_err(|e| assign_error(e, &name))?;
+        }
         Ok(v)
     }
 
+    fn visit_ternary(
+        &mut self,
+        condition: &Box<Expr>,
+        then_branch: &Box<Expr>,
+        else_branch: &Box<Expr>,
+    ) -> InterpreterResult {
+        if is_truthy(&condition.accept(self)?) {
+            then_branch.accept(self)
+        } else {
+            else_branch.accept(self)
+        }
+    }
+
+    fn visit_match(
+        &mut self,
+        keyword: &Token,
+        subject: &Box<Expr>,
+        arms: &Vec<MatchArm>,
+    ) -> InterpreterResult {
+        let value = subject.accept(self)?;
+
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if !match_pattern(&arm.pattern, &value, &mut bindings) {
+                continue;
+            }
+
+            let env = Environment::new_rc(Some(self.environment.clone()));
+            for (name, bound) in bindings {
+                env.borrow_mut().define(&name, bound);
+            }
+            if let Some(guard) = &arm.guard {
+                if !is_truthy(&self.evaluate_in(env.clone(), guard)?) {
+                    continue;
+                }
+            }
+            return self.evaluate_in(env, &arm.body);
+        }
+
+        Err(RuntimeError::NoMatch(value, keyword.clone()))
+    }
+
     fn visit_logical(
         &mut self,
-        left: Box<Expr>,
-        operator: Token,
-        right: Box<Expr>,
+        left: &Box<Expr>,
+        operator: &Token,
+        right: &Box<Expr>,
     ) -> InterpreterResult {
         let left = left.accept(self)?;
         let left_is_truthy = is_truthy(&left);
@@ -130,15 +425,17 @@ impl ExprVisitor<InterpreterResult> for LoxVisitor {
             TokenType::Or => right.accept(self),
             TokenType::And if left_is_truthy => right.accept(self),
             TokenType::And => Ok(left),
-            _ => Err(RuntimeError::InvalidLogicalOp(operator)),
+            TokenType::QuestionQuestion if left == LoxObject::Nil => right.accept(self),
+            TokenType::QuestionQuestion => Ok(left),
+            _ => Err(RuntimeError::InvalidLogicalOp(operator.clone())),
         }
     }
 
     fn visit_call(
         &mut self,
-        callee: Box<Expr>,
-        paren: Token,    // to do, use these...
-        args: Vec<Expr>, // to do, use these...
+        callee: &Box<Expr>,
+        paren: &Token,    // to do, use these...
+        args: &Vec<Expr>, // to do, use these...
     ) -> InterpreterResult {
         let mut eval_args = Vec::with_capacity(args.len());
 
@@ -147,52 +444,172 @@ impl ExprVisitor<InterpreterResult> for LoxVisitor {
         }
 
         match callee.accept(self)? {
-            LoxObject::Function(f) => f.call(self, &eval_args),
-            other => Err(RuntimeError::Uncallable(other, paren)),
+            LoxObject::Function(f) => self.call_function(&f, &eval_args, paren),
+            other => Err(RuntimeError::Uncallable(other, paren.clone())),
+        }
+    }
+
+    fn visit_list(&mut self, _bracket: &Token, elements: &Vec<Expr>) -> InterpreterResult {
+        let mut values = Vec::with_capacity(elements.len());
+
+        for element in elements {
+            values.push(element.accept(self)?);
+        }
+
+        Ok(LoxObject::list(values))
+    }
+
+    fn visit_index(
+        &mut self,
+        object: &Box<Expr>,
+        bracket: &Token,
+        index: &Box<Expr>,
+    ) -> InterpreterResult {
+        let object = object.accept(self)?;
+        let index = index.accept(self)?;
+        apply_index(object, bracket, index)
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Box<Expr>,
+        bracket: &Token,
+        index: &Box<Expr>,
+        value: &Box<Expr>,
+    ) -> InterpreterResult {
+        let object = object.accept(self)?;
+        let index = index.accept(self)?;
+        let value = value.accept(self)?;
+        assign_index(object, bracket, index, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_get(&mut self, object: &Box<Expr>, name: &Token) -> InterpreterResult {
+        get_property(object.accept(self)?, name)
+    }
+
+    fn visit_safe_get(&mut self, object: &Box<Expr>, name: &Token) -> InterpreterResult {
+        match object.accept(self)? {
+            LoxObject::Nil => Ok(LoxObject::Nil),
+            object => get_property(object, name),
+        }
+    }
+
+    fn visit_set(
+        &mut self,
+        object: &Box<Expr>,
+        name: &Token,
+        value: &Box<Expr>,
+    ) -> InterpreterResult {
+        let object = object.accept(self)?;
+        let value = value.accept(self)?;
+        match object {
+            LoxObject::Map(entries) => {
+                entries
+                    .borrow_mut()
+                    .insert(name.lexeme_or_empty(), value.clone());
+                Ok(value)
+            }
+            // a module's variables are only assigned by the module itself.
+            LoxObject::Module(_) => Err(RuntimeError::ConstReassignment(name.clone())),
+            other => Err(RuntimeError::InvalidProperty(other, name.clone())),
         }
     }
 
-    fn visit_function(&mut self, params: Vec<Token>, body: Vec<Stmt>) -> InterpreterResult {
-        let func = LoxFunction::new(None, params, body, self.environment.clone());
+    fn visit_map(&mut self, _brace: &Token, entries: &Vec<(Token, Expr)>) -> InterpreterResult {
+        let mut map = BTreeMap::new();
+
+        for (key, value) in entries {
+            map.insert(map_key(key), value.accept(self)?);
+        }
+
+        Ok(LoxObject::map(map))
+    }
+
+    fn visit_function(
+        &mut self,
+        params: &Vec<Param>,
+        _return_type: &Option<TypeAnnotation>,
+        body: &Rc<[Stmt]>,
+        captures: &Vec<Slot>,
+        is_generator: &bool,
+    ) -> InterpreterResult {
+        let func = LoxFunction::new(
+            None,
+            params,
+            body,
+            captures,
+            *is_generator,
+            &self.environment,
+        );
         Ok(LoxObject::Function(Rc::new(func)))
     }
 }
 
 impl StmtVisitor<InterpreterResult> for LoxVisitor {
-    fn visit_expression(&mut self, expression: Expr) -> InterpreterResult {
+    fn visit_expression(&mut self, expression: &Expr) -> InterpreterResult {
         expression.accept(self)?;
         Ok(LoxObject::Nil)
     }
 
-    fn visit_print(&mut self, expression: Expr) -> InterpreterResult {
+    fn visit_print(&mut self, expression: &Expr) -> InterpreterResult {
         let value = expression.accept(self)?;
-        println!("{}", value);
+        println!("{}", self.display(&value)?);
         Ok(LoxObject::Nil)
     }
 
-    fn visit_var(&mut self, name: Token, initializer: Option<Expr>) -> InterpreterResult {
+    fn visit_var(
+        &mut self,
+        name: &Token,
+        _annotation: &Option<TypeAnnotation>,
+        initializer: &Option<Expr>,
+    ) -> InterpreterResult {
         let value = initializer
+            .as_ref()
             .map(|e| e.accept(self))
             .unwrap_or(Ok(LoxObject::Nil))?;
 
-        //TODO - should check that the variable isn't declared already.
+        self.declare(name, value)?;
+        Ok(LoxObject::Nil)
+    }
+
+    fn visit_destructure(
+        &mut self,
+        keyword: &Token,
+        pattern: &Box<Pattern>,
+        initializer: &Expr,
+    ) -> InterpreterResult {
+        let value = initializer.accept(self)?;
+        for (name, bound) in destructure(pattern, &value, keyword)? {
+            self.declare(&name, bound)?;
+        }
+        Ok(LoxObject::Nil)
+    }
+
+    fn visit_const(
+        &mut self,
+        name: &Token,
+        _annotation: &Option<TypeAnnotation>,
+        initializer: &Expr,
+    ) -> InterpreterResult {
+        let value = initializer.accept(self)?;
         self.environment
             .borrow_mut()
-            .define(name.with_lexeme(|lex| lex.to_string()), value);
-
+            .declare_const(name, value)
+            .map_err(|e| assign_error(e, name))?;
         Ok(LoxObject::Nil)
     }
 
-    fn visit_block(&mut self, statements: Vec<Stmt>) -> InterpreterResult {
+    fn visit_block(&mut self, statements: &Vec<Stmt>) -> InterpreterResult {
         let new = self.create_new_environment();
-        self.execute_block(new, &statements)
+        self.execute_block(new, statements)
     }
 
     fn visit_if(
         &mut self,
-        condition: Expr,
-        then_branch: Box<Stmt>,
-        else_branch: Option<Box<Stmt>>,
+        condition: &Expr,
+        then_branch: &Box<Stmt>,
+        else_branch: &Option<Box<Stmt>>,
     ) -> InterpreterResult {
         let condition = condition.accept(self)?;
         if is_truthy(&condition) {
@@ -204,112 +621,432 @@ impl StmtVisitor<InterpreterResult> for LoxVisitor {
         }
     }
 
-    fn visit_while(&mut self, condition: Expr, body: Box<Stmt>) -> InterpreterResult {
+    fn visit_while(&mut self, condition: &Expr, body: &Box<Stmt>) -> InterpreterResult {
         while is_truthy(&condition.accept(self)?) {
-            if is_truthy(&body.accept(self)?) {
-                break;
+            match body.accept(self)? {
+                LoxObject::Break => break,
+                exit @ LoxObject::Exit(_) => return Ok(exit),
+                _ => {}
             }
         }
         Ok(LoxObject::Nil)
     }
 
-    fn visit_break(&mut self, _: Token) -> InterpreterResult {
-        Ok(LoxObject::Exit(Box::new(LoxObject::Nil)))
+    fn visit_for(
+        &mut self,
+        initializer: &Option<Box<Stmt>>,
+        condition: &Expr,
+        increment: &Option<Expr>,
+        body: &Box<Stmt>,
+    ) -> InterpreterResult {
+        let env = self.create_new_environment();
+        if let Some(initializer) = initializer {
+            self.execute_block(env.clone(), std::slice::from_ref(initializer))?;
+        }
+
+        while is_truthy(&self.evaluate_in(env.clone(), condition)?) {
+            match self.execute_block(env.clone(), std::slice::from_ref(body))? {
+                LoxObject::Break => break,
+                exit @ LoxObject::Exit(_) => return Ok(exit),
+                _ => {}
+            }
+            // the next iteration (starting with the increment) works on
+            // copies, closures created in the body keep this iteration's values.
+            env.borrow_mut().refresh();
+            if let Some(increment) = &increment {
+                self.evaluate_in(env.clone(), increment)?;
+            }
+        }
+        Ok(LoxObject::Nil)
     }
 
-    fn visit_return(&mut self, _keyword: Token, value: Option<Expr>) -> InterpreterResult {
+    fn visit_for_in(
+        &mut self,
+        keyword: &Token,
+        name: &Token,
+        iterable: &Expr,
+        body: &Box<Stmt>,
+    ) -> InterpreterResult {
+        let iterable = iterable.accept(self)?;
+        let mut iterator = LoxIterator::new(iterable, keyword)?;
+
+        while let Some(value) = iterator.next(self, keyword)? {
+            let env = self.create_new_environment();
+            env.borrow_mut().define(name, value);
+            match self.execute_block(env, std::slice::from_ref(body))? {
+                LoxObject::Break => break,
+                exit @ LoxObject::Exit(_) => return Ok(exit),
+                _ => {}
+            }
+        }
+        Ok(LoxObject::Nil)
+    }
+
+    fn visit_break(&mut self, _: &Token) -> InterpreterResult {
+        Ok(LoxObject::Break)
+    }
+
+    // generators run yields themselves (see Generator), the resolver rejects
+    // them anywhere else.
+    fn visit_yield(
+        &mut self,
+        keyword: &Token,
+        _value: &Option<Expr>,
+        _target: &Option<Token>,
+        _annotation: &Option<TypeAnnotation>,
+    ) -> InterpreterResult {
+        Err(RuntimeError::Native(format!(
+            "\"yield\" outside of a generator {}",
+            keyword.coordinate
+        )))
+    }
+
+    fn visit_return(&mut self, _keyword: &Token, value: &Option<Expr>) -> InterpreterResult {
         Ok(LoxObject::Exit(Box::new(
             value
+                .as_ref()
                 .map(|v| v.accept(self))
                 .unwrap_or(Ok(LoxObject::Nil))?,
         )))
     }
 
+    fn visit_import(
+        &mut self,
+        keyword: &Token,
+        path: &Token,
+        alias: &Option<Token>,
+        names: &Vec<Token>,
+    ) -> InterpreterResult {
+        let path = match &path.literal {
+            Literal::String(s) => s.clone(),
+            _ => path.lexeme_or_empty(),
+        };
+        let module = self.import_module(keyword, &path)?;
+
+        if let Some(alias) = alias {
+            self.declare(alias, module)?;
+        } else if let LoxObject::Module(env) = module {
+            for name in names {
+                let value = name
+                    .with_lexeme(|key| env.borrow().binding(key))
+                    .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone()))?;
+                self.declare(name, value)?;
+            }
+        }
+
+        Ok(LoxObject::Nil)
+    }
+
+    fn visit_throw(&mut self, keyword: &Token, value: &Expr) -> InterpreterResult {
+        let value = value.accept(self)?;
+        Err(RuntimeError::Thrown(value, keyword.clone()))
+    }
+
+    fn visit_try(
+        &mut self,
+        _keyword: &Token,
+        body: &Vec<Stmt>,
+        catch_name: &Option<Token>,
+        catch_body: &Option<Vec<Stmt>>,
+        finally_body: &Option<Vec<Stmt>>,
+    ) -> InterpreterResult {
+        let env = self.create_new_environment();
+        let mut result = self.execute_block(env, body);
+
+        if let (Err(e), Some(name), Some(catch_body)) = (&result, catch_name, catch_body) {
+            if e.is_catchable() {
+                let env = self.create_new_environment();
+                env.borrow_mut().define(name, error_value(e.clone()));
+                result = self.execute_block(env, catch_body);
+            }
+        }
+
+        if let Some(finally_body) = finally_body {
+            let env = self.create_new_environment();
+            // a return or break inside of finally wins over the try/catch outcome.
+            if let exit @ (LoxObject::Exit(_) | LoxObject::Break) =
+                self.execute_block(env, finally_body)?
+            {
+                return Ok(exit);
+            }
+        }
+
+        result
+    }
+
     fn visit_function(
         &mut self,
-        name: Token,
-        params: Vec<Token>,
-        body: Vec<Stmt>,
+        name: &Token,
+        params: &Vec<Param>,
+        _return_type: &Option<TypeAnnotation>,
+        body: &Rc<[Stmt]>,
+        captures: &Vec<Slot>,
+        is_generator: &bool,
     ) -> InterpreterResult {
-        let map_key_name = name.lexeme_or_empty();
-        let func = LoxFunction::new(Some(name), params, body, self.environment.clone());
+        // declared first so that the function captures its own name and can recurse.
+        self.declare(name, LoxObject::Nil)?;
+        let func = LoxFunction::new(
+            Some(name.clone()),
+            params,
+            body,
+            captures,
+            *is_generator,
+            &self.environment,
+        );
         self.environment
             .borrow_mut()
-            .define(map_key_name, LoxObject::Function(Rc::new(func)));
+            .assign(name, LoxObject::Function(Rc::new(func)))
+            .map_err(|e| assign_error(e, name))?;
         Ok(LoxObject::Nil)
     }
 }
 
-fn either_is_string(left: &LoxObject, right: &LoxObject) -> bool {
-    match (left, right) {
-        (LoxObject::String(_), _) => true,
-        (_, LoxObject::String(_)) => true,
-        _ => false,
+// a function stored under one of the "__" hook names of a map.
+fn method(value: &LoxObject, name: &str) -> Option<Rc<dyn Callable>> {
+    match value {
+        LoxObject::Map(entries) => match entries.borrow().get(name) {
+            Some(LoxObject::Function(f)) => Some(f.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn get_property(object: LoxObject, name: &Token) -> InterpreterResult {
+    match object {
+        LoxObject::Map(entries) => Ok(
+            name.with_lexeme(|key| entries.borrow().get(key).cloned().unwrap_or(LoxObject::Nil))
+        ),
+        LoxObject::Module(env) => Ok(name
+            .with_lexeme(|key| env.borrow().binding(key))
+            .unwrap_or(LoxObject::Nil)),
+        other => Err(RuntimeError::InvalidProperty(other, name.clone())),
     }
 }
 
+fn assign_error(e: AssignError, name: &Token) -> RuntimeError {
+    match e {
+        AssignError::Undefined => RuntimeError::UndefinedVariable(name.clone()),
+        AssignError::Const => RuntimeError::ConstReassignment(name.clone()),
+    }
+}
+
+fn either_is_string(left: &LoxObject, right: &LoxObject) -> bool {
+    matches!(
+        (left, right),
+        (LoxObject::String(_), _) | (_, LoxObject::String(_))
+    )
+}
+
 fn concatenate(left: LoxObject, right: LoxObject) -> String {
     format!("{}{}", left, right)
 }
 
+// why an operator couldn't be applied, see get_binary_error.
+enum OpError {
+    Operands,
+    Overflow,
+    DivisionByZero,
+}
+
+// integer operands go through int (which checks for overflow), any other mix
+// of numbers is promoted to floats.
 fn math_op_with_check(
     left: &LoxObject,
     right: &LoxObject,
-    f: fn(f64, f64) -> f64,
-) -> Result<LoxObject, ()> {
-    match (left, right) {
-        (LoxObject::Number(a), LoxObject::Number(b)) => Ok(LoxObject::Number(f(*a, *b))),
-        _ => Err(()),
+    int: fn(i64, i64) -> Result<LoxObject, OpError>,
+    float: fn(f64, f64) -> f64,
+) -> Result<LoxObject, OpError> {
+    if let (LoxObject::Integer(a), LoxObject::Integer(b)) = (left, right) {
+        return int(*a, *b);
+    }
+    match (left.as_number(), right.as_number()) {
+        (Some(a), Some(b)) => Ok(LoxObject::Number(float(a, b))),
+        _ => Err(OpError::Operands),
+    }
+}
+
+fn checked(result: Option<i64>) -> Result<LoxObject, OpError> {
+    result.map(LoxObject::Integer).ok_or(OpError::Overflow)
+}
+
+fn nonzero(divisor: i64) -> Result<i64, OpError> {
+    if divisor == 0 {
+        Err(OpError::DivisionByZero)
+    } else {
+        Ok(divisor)
+    }
+}
+
+// floored like python, so that a == (a // b) * b + a % b holds for negative
+// operands too.
+fn floor_div(a: i64, b: i64) -> Result<LoxObject, OpError> {
+    let b = nonzero(b)?;
+    let q = a.checked_div(b).ok_or(OpError::Overflow)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Ok(LoxObject::Integer(q - 1))
+    } else {
+        Ok(LoxObject::Integer(q))
+    }
+}
+
+fn floor_mod(a: i64, b: i64) -> Result<LoxObject, OpError> {
+    let b = nonzero(b)?;
+    // only i64::MIN % -1 overflows, and its remainder is 0.
+    let r = a.checked_rem(b).unwrap_or(0);
+    if r != 0 && (r < 0) != (b < 0) {
+        Ok(LoxObject::Integer(r + b))
+    } else {
+        Ok(LoxObject::Integer(r))
+    }
+}
+
+// a negative exponent gives a float, like 2 ** -1 == 0.5.
+fn int_pow(a: i64, b: i64) -> Result<LoxObject, OpError> {
+    if b < 0 {
+        return Ok(LoxObject::Number((a as f64).powf(b as f64)));
+    }
+    let b = u32::try_from(b).map_err(|_| OpError::Overflow)?;
+    checked(a.checked_pow(b))
+}
+
+// the bitwise operators only accept numbers with an integral value, f returns
+// None when the operation itself is invalid (e.g. shifting by 64 or more).
+fn integer_op_with_check(
+    left: &LoxObject,
+    right: &LoxObject,
+    f: fn(i64, i64) -> Option<i64>,
+) -> Result<LoxObject, OpError> {
+    match (as_integer(left), as_integer(right)) {
+        (Some(a), Some(b)) => f(a, b).map(LoxObject::Integer).ok_or(OpError::Operands),
+        _ => Err(OpError::Operands),
+    }
+}
+
+fn as_integer(value: &LoxObject) -> Option<i64> {
+    match value {
+        LoxObject::Integer(i) => Some(*i),
+        LoxObject::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Some(*n as i64),
+        _ => None,
     }
 }
 
 fn math_compare_with_check(
     left: &LoxObject,
     right: &LoxObject,
-    f: fn(f64, f64) -> bool,
-) -> Result<LoxObject, ()> {
-    match (left, right) {
-        (LoxObject::Number(a), LoxObject::Number(b)) => Ok(LoxObject::Boolean(f(*a, *b))),
-        _ => Err(()),
+    f: fn(Ordering) -> bool,
+) -> Result<LoxObject, OpError> {
+    // compare integers exactly, they might not survive the trip through a float.
+    if let (LoxObject::Integer(a), LoxObject::Integer(b)) = (left, right) {
+        return Ok(LoxObject::Boolean(f(a.cmp(b))));
+    }
+    match (left.as_number(), right.as_number()) {
+        // any comparison involving NaN is false.
+        (Some(a), Some(b)) => Ok(LoxObject::Boolean(a.partial_cmp(&b).is_some_and(f))),
+        _ => Err(OpError::Operands),
     }
 }
 
-fn apply_binary(left: LoxObject, operator: Token, right: LoxObject) -> InterpreterResult {
+fn apply_binary(left: LoxObject, operator: &Token, right: LoxObject) -> InterpreterResult {
     let result = match operator.token_type {
         TokenType::Plus => {
             if either_is_string(&left, &right) {
                 return Ok(LoxObject::String(concatenate(left, right)));
             }
-            math_op_with_check(&left, &right, |a, b| a + b)
+            math_op_with_check(
+                &left,
+                &right,
+                |a, b| checked(a.checked_add(b)),
+                |a, b| a + b,
+            )
+        }
+        TokenType::Minus => math_op_with_check(
+            &left,
+            &right,
+            |a, b| checked(a.checked_sub(b)),
+            |a, b| a - b,
+        ),
+        TokenType::Star => math_op_with_check(
+            &left,
+            &right,
+            |a, b| checked(a.checked_mul(b)),
+            |a, b| a * b,
+        ),
+        TokenType::Slash => math_op_with_check(
+            &left,
+            &right,
+            |a, b| Ok(LoxObject::Number(a as f64 / b as f64)),
+            |a, b| a / b,
+        ),
+        TokenType::Percent => {
+            math_op_with_check(&left, &right, floor_mod, |a, b| a - b * (a / b).floor())
+        }
+        TokenType::TildeSlash => {
+            math_op_with_check(&left, &right, floor_div, |a, b| (a / b).floor())
         }
-        TokenType::Minus => math_op_with_check(&left, &right, |a, b| a - b),
-        TokenType::Star => math_op_with_check(&left, &right, |a, b| a * b),
-        TokenType::Slash => math_op_with_check(&left, &right, |a, b| a / b),
-        TokenType::Greater => math_compare_with_check(&left, &right, |a, b| a > b),
-        TokenType::GreaterEqual => math_compare_with_check(&left, &right, |a, b| a >= b),
-        TokenType::Less => math_compare_with_check(&left, &right, |a, b| a < b),
-        TokenType::LessEqual => math_compare_with_check(&left, &right, |a, b| a <= b),
+        TokenType::StarStar => math_op_with_check(&left, &right, int_pow, f64::powf),
+        TokenType::Ampersand => integer_op_with_check(&left, &right, |a, b| Some(a & b)),
+        TokenType::Pipe => integer_op_with_check(&left, &right, |a, b| Some(a | b)),
+        TokenType::Caret => integer_op_with_check(&left, &right, |a, b| Some(a ^ b)),
+        TokenType::LessLess => integer_op_with_check(&left, &right, |a, b| {
+            u32::try_from(b).ok().and_then(|b| a.checked_shl(b))
+        }),
+        TokenType::GreaterGreater => integer_op_with_check(&left, &right, |a, b| {
+            u32::try_from(b).ok().and_then(|b| a.checked_shr(b))
+        }),
+        TokenType::Greater => math_compare_with_check(&left, &right, Ordering::is_gt),
+        TokenType::GreaterEqual => math_compare_with_check(&left, &right, Ordering::is_ge),
+        TokenType::Less => math_compare_with_check(&left, &right, Ordering::is_lt),
+        TokenType::LessEqual => math_compare_with_check(&left, &right, Ordering::is_le),
         TokenType::BangEqual => Ok(LoxObject::Boolean(left != right)),
         TokenType::EqualEqual => Ok(LoxObject::Boolean(left == right)),
         _ => panic!("Unrecoverable error: invalid operator in binary expression."),
     };
 
-    if result.is_err() {
-        return get_binary_error(left, operator, right);
-    }
-
-    Ok(result.unwrap().into())
+    result.or_else(|e| get_binary_error(e, left, operator, right))
 }
 
-fn get_binary_error(left: LoxObject, operator: Token, right: LoxObject) -> InterpreterResult {
+fn get_binary_error(
+    error: OpError,
+    left: LoxObject,
+    operator: &Token,
+    right: LoxObject,
+) -> InterpreterResult {
+    let reason = match error {
+        OpError::Operands => None,
+        OpError::Overflow => Some("integer overflow"),
+        OpError::DivisionByZero => Some("integer division by zero"),
+    };
+    if let Some(reason) = reason {
+        return Err(RuntimeError::ArithmeticError(
+            format!("{}", left),
+            operator.clone(),
+            format!("{}", right),
+            reason,
+        ));
+    }
+
     match operator.token_type {
-        TokenType::Plus | TokenType::Minus | TokenType::Star | TokenType::Slash => Err(
-            RuntimeError::InvalidMathOp(format!("{}", left), operator, format!("{}", right)),
-        ),
+        TokenType::Plus
+        | TokenType::Minus
+        | TokenType::Star
+        | TokenType::Slash
+        | TokenType::Percent
+        | TokenType::TildeSlash
+        | TokenType::StarStar
+        | TokenType::Ampersand
+        | TokenType::Pipe
+        | TokenType::Caret
+        | TokenType::LessLess
+        | TokenType::GreaterGreater => Err(RuntimeError::InvalidMathOp(
+            format!("{}", left),
+            operator.clone(),
+            format!("{}", right),
+        )),
         TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
             Err(RuntimeError::InvalidComparisonOp(
                 format!("{}", left),
-                operator,
+                operator.clone(),
                 format!("{}", right),
             ))
         }
@@ -317,22 +1054,765 @@ fn get_binary_error(left: LoxObject, operator: Token, right: LoxObject) -> Inter
     }
 }
 
-fn apply_unary(operator: Token, right: LoxObject) -> InterpreterResult {
+// what a "catch" clause binds, thrown values are passed through untouched
+// while interpreter errors become a map describing the failure.
+fn error_value(error: RuntimeError) -> LoxObject {
+    if let RuntimeError::Thrown(value, _) = error {
+        return value;
+    }
+
+    let line = error
+        .coordinate()
+        .map(|c| LoxObject::Integer(c.line as i64))
+        .unwrap_or(LoxObject::Nil);
+
+    let mut map = BTreeMap::new();
+    map.insert(
+        "kind".to_string(),
+        LoxObject::String(error.kind().to_string()),
+    );
+    map.insert("message".to_string(), LoxObject::String(error.message()));
+    map.insert("line".to_string(), line);
+    LoxObject::map(map)
+}
+
+fn apply_index(object: LoxObject, bracket: &Token, index: LoxObject) -> InterpreterResult {
+    // maps are keyed by strings and give back nil for a missing key.
+    if let LoxObject::Map(ref entries) = object {
+        return match index {
+            LoxObject::String(ref key) => {
+                Ok(entries.borrow().get(key).cloned().unwrap_or(LoxObject::Nil))
+            }
+            _ => Err(RuntimeError::InvalidIndex(object, bracket.clone(), index)),
+        };
+    }
+
+    let i = match as_integer(&index) {
+        Some(i) => i as f64,
+        None => return Err(RuntimeError::InvalidIndex(object, bracket.clone(), index)),
+    };
+
+    match object {
+        LoxObject::List(ref values) => {
+            let values = values.borrow();
+            if i < 0.0 || i as usize >= values.len() {
+                return Err(RuntimeError::IndexOutOfRange(
+                    bracket.clone(),
+                    i,
+                    values.len(),
+                ));
+            }
+            Ok(values[i as usize].clone())
+        }
+        LoxObject::String(ref s) => {
+            let ch = if i < 0.0 {
+                None
+            } else {
+                s.chars().nth(i as usize)
+            };
+            ch.map(|c| LoxObject::String(c.to_string()))
+                .ok_or_else(|| RuntimeError::IndexOutOfRange(bracket.clone(), i, s.chars().count()))
+        }
+        _ => Err(RuntimeError::InvalidIndex(object, bracket.clone(), index)),
+    }
+}
+
+fn assign_index(
+    object: LoxObject,
+    bracket: &Token,
+    index: LoxObject,
+    value: LoxObject,
+) -> Result<(), RuntimeError> {
+    match (&object, &index) {
+        (LoxObject::Map(entries), LoxObject::String(key)) => {
+            entries.borrow_mut().insert(key.clone(), value);
+            Ok(())
+        }
+        (LoxObject::List(values), _) => {
+            let Some(i) = as_integer(&index) else {
+                return Err(RuntimeError::InvalidIndex(object, bracket.clone(), index));
+            };
+            let mut values = values.borrow_mut();
+            if i < 0 || i as usize >= values.len() {
+                return Err(RuntimeError::IndexOutOfRange(
+                    bracket.clone(),
+                    i as f64,
+                    values.len(),
+                ));
+            }
+            values[i as usize] = value;
+            Ok(())
+        }
+        _ => Err(RuntimeError::InvalidIndex(object, bracket.clone(), index)),
+    }
+}
+
+fn apply_unary(operator: &Token, right: LoxObject) -> InterpreterResult {
     match operator.token_type {
         TokenType::Minus => match right {
+            LoxObject::Integer(i) if i != i64::MIN => Ok(LoxObject::Integer(-i)),
             LoxObject::Number(n) => Ok(LoxObject::Number(-n)),
-            _ => Err(RuntimeError::InvalidUnaryOp(operator, format!("{}", right))),
+            _ => Err(RuntimeError::InvalidUnaryOp(
+                operator.clone(),
+                format!("{}", right),
+            )),
         },
 
-        TokenType::Bang => Ok(LoxObject::Boolean(!is_truthy(&right.into()))),
+        TokenType::Bang => Ok(LoxObject::Boolean(!is_truthy(&right))),
+        TokenType::Tilde => match as_integer(&right) {
+            Some(n) => Ok(LoxObject::Integer(!n)),
+            None => Err(RuntimeError::InvalidUnaryOp(
+                operator.clone(),
+                format!("{}", right),
+            )),
+        },
         _ => panic!("Unrecoverable error: invalid operator in unary expression."),
     }
 }
 
-fn is_truthy(literal: &LoxObject) -> bool {
+// the names a destructuring pattern binds, or an error if the value doesn't fit.
+fn destructure(
+    pattern: &Pattern,
+    value: &LoxObject,
+    tok: &Token,
+) -> Result<Vec<(Token, LoxObject)>, RuntimeError> {
+    let mut bindings = Vec::new();
+    if !match_pattern(pattern, value, &mut bindings) {
+        return Err(RuntimeError::InvalidDestructure(value.clone(), tok.clone()));
+    }
+    Ok(bindings)
+}
+
+// whether the value fits the pattern, collecting what it binds along the way.
+fn match_pattern(
+    pattern: &Pattern,
+    value: &LoxObject,
+    bindings: &mut Vec<(Token, LoxObject)>,
+) -> bool {
+    match pattern {
+        Pattern::Wildcard(_) => true,
+        Pattern::Binding(name) => {
+            bindings.push((name.clone(), value.clone()));
+            true
+        }
+        Pattern::Literal(tok) => LoxObject::from(tok.literal.clone()) == *value,
+        Pattern::Alternatives(alternatives) => alternatives
+            .iter()
+            .any(|p| match_pattern(p, value, bindings)),
+        Pattern::List { elements, rest, .. } => {
+            let LoxObject::List(list) = value else {
+                return false;
+            };
+            let list = list.borrow();
+            let fits = match rest {
+                Some(_) => list.len() >= elements.len(),
+                None => list.len() == elements.len(),
+            };
+            if !fits
+                || !elements
+                    .iter()
+                    .zip(list.iter())
+                    .all(|(p, v)| match_pattern(p, v, bindings))
+            {
+                return false;
+            }
+            if let Some(rest) = rest {
+                let remaining = list[elements.len()..].to_vec();
+                bindings.push((rest.clone(), LoxObject::list(remaining)));
+            }
+            true
+        }
+        Pattern::Map { entries, .. } => {
+            let LoxObject::Map(map) = value else {
+                return false;
+            };
+            let map = map.borrow();
+            entries.iter().all(|(key, p)| {
+                map.get(&map_key(key))
+                    .is_some_and(|v| match_pattern(p, v, bindings))
+            })
+        }
+    }
+}
+
+pub fn is_truthy(literal: &LoxObject) -> bool {
     match literal {
         LoxObject::Boolean(b) => *b,
         LoxObject::Nil => false,
         _ => true,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{run, s};
+
+    fn global(interpreter: &LoxVisitor, name: &str) -> LoxObject {
+        interpreter.environment.borrow().global(name).unwrap()
+    }
+
+    #[test]
+    fn test_cyclic_collections() {
+        let (interpreter, result) = run("var l = [1]; l[0] = l;
+            var m = {}; m[\"self\"] = m;
+            var n = {}; n[\"self\"] = n;
+            var same = [m == n, l == l, [l] == [l]];");
+        assert_eq!(result, Ok(()));
+        let (l, m, n) = (
+            global(&interpreter, "l"),
+            global(&interpreter, "m"),
+            global(&interpreter, "n"),
+        );
+        assert_eq!(l.to_string(), "[[...]]");
+        assert_eq!(m.to_string(), "{\"self\": {...}}");
+        assert_eq!(
+            global(&interpreter, "same"),
+            LoxObject::list(vec![
+                LoxObject::Boolean(false),
+                LoxObject::Boolean(true),
+                LoxObject::Boolean(true)
+            ])
+        );
+
+        // the same list appearing twice without a cycle is printed in full.
+        let (interpreter, result) = run("var a = [1]; var b = [a, a];");
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "b").to_string(), "[[1], [1]]");
+
+        // break the cycles so the collections are freed.
+        for v in [l, m, n] {
+            match v {
+                LoxObject::List(values) => values.borrow_mut().clear(),
+                LoxObject::Map(entries) => entries.borrow_mut().clear(),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_catch_runtime_error() {
+        let (interpreter, result) = run("var kind; var line; var message;
+            try { 1 + nil; } catch (e) { kind = e.kind; line = e.line; message = e.message; }");
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "kind"), s("TypeError"));
+        assert_eq!(global(&interpreter, "line"), LoxObject::Number(2.0));
+        // the coordinate is only in "line", not repeated in the message.
+        assert_eq!(
+            global(&interpreter, "message"),
+            s("Invalid math operation \"1 + nil\"")
+        );
+    }
+
+    #[test]
+    fn test_native_errors_carry_one_coordinate() {
+        let (_, result) = run("var v = json_parse(\"[tru]\");");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "RuntimeError: \"json_parse\" invalid literal, expected \"true\" at line 1, column 5 @(1:27)"
+        );
+    }
+
+    #[test]
+    fn test_catch_thrown_value_and_finally() {
+        let (interpreter, result) = run(
+            "var log = [nil, nil];
+            fun f() {
+                try { throw \"boom\"; } catch (e) { log[0] = e; return 1; } finally { log[1] = \"done\"; }
+            }
+            var r = f();",
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "r"), LoxObject::Number(1.0));
+        assert_eq!(
+            global(&interpreter, "log"),
+            LoxObject::list(vec![s("boom"), s("done")])
+        );
+    }
+
+    #[test]
+    fn test_uncaught_and_uncatchable_errors() {
+        let (_, result) = run("try { throw 1; } finally { }");
+        assert!(matches!(result, Err(RuntimeError::Thrown(..))));
+
+        let (interpreter, result) = run("var x = 1; try { exit(2); } catch (e) { x = 2; }");
+        assert_eq!(result, Err(RuntimeError::Exit(2)));
+        assert_eq!(global(&interpreter, "x"), LoxObject::Number(1.0));
+    }
+
+    #[test]
+    fn test_arity_mismatch_is_catchable() {
+        let (interpreter, result) = run("fun f(a) { return a; } var kind;
+            try { f(1, 2); } catch (e) { kind = e.kind; }");
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "kind"), s("ArityError"));
+    }
+
+    #[test]
+    fn test_const_checked_at_runtime() {
+        // the resolver can't know that x will be a const when f is declared.
+        let (interpreter, result) = run("fun f() { x = 2; } const x = 1; f();");
+        assert!(matches!(result, Err(RuntimeError::ConstReassignment(_))));
+        assert_eq!(global(&interpreter, "x"), LoxObject::Number(1.0));
+
+        let (_, result) = run("const x = 1; fun f() { var x = 2; x = 3; return x; } var y = f();");
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_conditional_operators() {
+        let (interpreter, result) = run("var calls = 0;
+            fun side() { calls = calls + 1; return 10; }
+            var a = true ? 1 : side();
+            var b = nil ?? 2;
+            var c = false ?? side();
+            var d = 3 ?? side();
+            var e = false ? 1 : nil ? 2 : 3;
+            var m = {inner: {x: 4}};
+            var f = m?.inner?.x;
+            var g = m.missing?.x ?? 5;");
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "calls"), LoxObject::Number(0.0));
+        assert_eq!(global(&interpreter, "a"), LoxObject::Number(1.0));
+        assert_eq!(global(&interpreter, "b"), LoxObject::Number(2.0));
+        assert_eq!(global(&interpreter, "c"), LoxObject::Boolean(false));
+        assert_eq!(global(&interpreter, "d"), LoxObject::Number(3.0));
+        assert_eq!(global(&interpreter, "e"), LoxObject::Number(3.0));
+        assert_eq!(global(&interpreter, "f"), LoxObject::Number(4.0));
+        assert_eq!(global(&interpreter, "g"), LoxObject::Number(5.0));
+
+        let (_, result) = run("var n = 1; var x = n?.field;");
+        assert!(matches!(result, Err(RuntimeError::InvalidProperty(..))));
+    }
+
+    #[test]
+    fn test_default_and_rest_parameters() {
+        let (interpreter, result) = run("var calls = 0;
+            fun next() { calls = calls + 1; return calls; }
+            fun f(a, b = a * 2, c = next(), ...rest) { return [a, b, c, rest]; }
+            var x = f(1);
+            var y = f(1, 5);
+            var z = f(1, 2, 3, 4, 5);
+            var g = fun (...all) { return len(all); };
+            var n = g();");
+        assert_eq!(result, Ok(()));
+        let list = |values: Vec<LoxObject>| LoxObject::list(values);
+        let num = LoxObject::Integer;
+        assert_eq!(
+            global(&interpreter, "x"),
+            list(vec![num(1), num(2), num(1), list(vec![])])
+        );
+        assert_eq!(
+            global(&interpreter, "y"),
+            list(vec![num(1), num(5), num(2), list(vec![])])
+        );
+        assert_eq!(
+            global(&interpreter, "z"),
+            list(vec![num(1), num(2), num(3), list(vec![num(4), num(5)])])
+        );
+        assert_eq!(global(&interpreter, "n"), num(0));
+
+        let (_, result) = run("fun f(a, b = 1) {} f();");
+        match result {
+            Err(e @ RuntimeError::ArityMismatch(_, 1, Some(2), 0)) => {
+                assert!(e
+                    .to_string()
+                    .contains("Expected 1 to 2 arguments but got 0"))
+            }
+            other => panic!("expected an arity error, got {:?}", other),
+        }
+        let (_, result) = run("fun f(a, ...b) {} f();");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::ArityMismatch(_, 1, None, 0))
+        ));
+        let (_, result) = run("fun f(a) {} f(1, 2);");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::ArityMismatch(_, 1, Some(1), 2))
+        ));
+    }
+
+    #[test]
+    fn test_arrow_functions() {
+        let (interpreter, result) = run(
+            "fun map(list, f) { return [f(list[0]), f(list[1]), f(list[2])]; }
+            var doubled = map([1, 2, 3], (x) => x * 2);
+            var add = (a, b = 10) => a + b;
+            var sum = add(1) + add(1, 2);
+            var counter = () => { var n = 0; return () => n = n + 1; };
+            var c = counter(); c();
+            var count = c();",
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            global(&interpreter, "doubled"),
+            LoxObject::list(vec![
+                LoxObject::Integer(2),
+                LoxObject::Integer(4),
+                LoxObject::Integer(6)
+            ])
+        );
+        assert_eq!(global(&interpreter, "sum"), LoxObject::Integer(14));
+        assert_eq!(global(&interpreter, "count"), LoxObject::Integer(2));
+    }
+
+    #[test]
+    fn test_match_expressions() {
+        let (interpreter, result) = run("fun describe(v) {
+                return match (v) {
+                    0 => \"zero\",
+                    \"a\" | \"b\" => \"letter\",
+                    n if n == -3 => \"negative\",
+                    [] => \"empty\",
+                    [x, y] => x + y,
+                    [first, ...rest] => len(rest),
+                    {name: \"lox\", version} => version,
+                    _ => \"other\",
+                };
+            }
+            var r = [describe(0), describe(\"b\"), describe(-3), describe([]),
+                describe([1, 2]), describe([1, 2, 3]), describe({name: \"lox\", version: 2}),
+                describe({name: \"rust\"}), describe(0.0)];");
+        assert_eq!(result, Ok(()));
+        let string = |s: &str| LoxObject::String(s.to_string());
+        assert_eq!(
+            global(&interpreter, "r"),
+            LoxObject::list(vec![
+                string("zero"),
+                string("letter"),
+                string("negative"),
+                string("empty"),
+                LoxObject::Integer(3),
+                LoxObject::Integer(2),
+                LoxObject::Integer(2),
+                string("other"),
+                string("zero"),
+            ])
+        );
+
+        // a parenthesized guard isn't an arrow function's parameter list.
+        let (interpreter, result) = run("var size = (v) => match (v) {
+                x if (x > 1) => \"big\",
+                _ => \"small\",
+            };
+            var r = [size(5), size(0)];");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            global(&interpreter, "r"),
+            LoxObject::list(vec![string("big"), string("small")])
+        );
+
+        // bindings are scoped to their arm.
+        let (_, result) = run("var x = 1; var y = match (5) { x => x }; print x + y;");
+        assert_eq!(result, Ok(()));
+        let (_, result) = run("var y = match (5) { x => x }; print x;");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(..))));
+
+        let (_, result) = run("var v = match (3) { 1 => 1, 2 => 2 };");
+        match result {
+            Err(e @ RuntimeError::NoMatch(..)) => {
+                assert_eq!(e.kind(), "MatchError");
+                assert!(e
+                    .to_string()
+                    .contains("No match arm for integer value \"3\""));
+            }
+            other => panic!("expected a match error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let (interpreter, result) = run("fun divmod(a, b) { return [a ~/ b, a % b]; }
+            var [q, r] = divmod(17, 5);
+            var {name, \"age\": years} = {name: \"lox\", age: 3, extra: nil};
+            var [head, _, ...tail] = [1, 2, 3, 4];
+            var a = 1; var b = 2;
+            [a, b] = [b, a];
+            // a statement starting with \"{\" is a block, so this one is grouped.
+            ({x: a} = {x: 10});");
+        assert_eq!(result, Ok(()));
+        for (name, value) in [
+            ("q", LoxObject::Integer(3)),
+            ("r", LoxObject::Integer(2)),
+            ("name", LoxObject::String("lox".to_string())),
+            ("years", LoxObject::Integer(3)),
+            ("head", LoxObject::Integer(1)),
+            (
+                "tail",
+                LoxObject::list(vec![LoxObject::Integer(3), LoxObject::Integer(4)]),
+            ),
+            ("a", LoxObject::Integer(10)),
+            ("b", LoxObject::Integer(1)),
+        ] {
+            assert_eq!(global(&interpreter, name), value, "{}", name);
+        }
+
+        let (_, result) = run("var [a, b] = [1];");
+        match result {
+            Err(e @ RuntimeError::InvalidDestructure(..)) => {
+                assert_eq!(e.kind(), "TypeError");
+                assert!(e.to_string().contains("Cannot destructure list value"));
+            }
+            other => panic!("expected a destructuring error, got {:?}", other),
+        }
+        let (_, result) = run("var {missing} = {};");
+        assert!(matches!(result, Err(RuntimeError::InvalidDestructure(..))));
+        let (_, result) = run("[undeclared] = [1];");
+        assert!(matches!(result, Err(RuntimeError::UndefinedVariable(..))));
+    }
+
+    #[test]
+    fn test_for_in_and_loop_exits() {
+        let (interpreter, result) = run("var total = 0; var keys = \"\"; var chars = \"\";
+            for (var x in [1, 2, 3]) total = total + x;
+            for (var k in {b: 1, a: 2}) keys = keys + k;
+            for (var c in \"lox\") { if (c == \"x\") break; chars = chars + c; }
+            fun find(list, wanted) {
+                var i = 0;
+                while (i < len(list)) { if (list[i] == wanted) return i; i = i + 1; }
+                return -1;
+            }
+            fun first_even(list) { for (var x in list) if (x % 2 == 0) return x; }
+            var found = [find([5, 6, 7], 7), first_even([1, 3, 4, 6])];");
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "total"), LoxObject::Integer(6));
+        assert_eq!(global(&interpreter, "keys"), s("ab"));
+        assert_eq!(global(&interpreter, "chars"), s("lo"));
+        assert_eq!(
+            global(&interpreter, "found"),
+            LoxObject::list(vec![LoxObject::Integer(2), LoxObject::Integer(4)])
+        );
+    }
+
+    #[test]
+    fn test_operator_overloading() {
+        let (interpreter, result) = run("fun vec(x, y) {
+                return {x: x, y: y,
+                    __add: (a, b) => vec(a.x + b.x, a.y + b.y),
+                    __sub: (a, b) => vec(a.x - b.x, a.y - b.y),
+                    __mul: (a, k) => vec(a.x * k, a.y * k),
+                    __eq: (a, b) => a.x == b.x and a.y == b.y,
+                    __str: (v) => \"<\" + str(v.x) + \", \" + str(v.y) + \">\"};
+            }
+            fun money(cents) {
+                return {cents: cents,
+                    __lt: (a, b) => a.cents < b.cents,
+                    __le: (a, b) => a.cents <= b.cents,
+                    __str: (m) => \"$\" + str(m.cents / 100)};
+            }
+            var sum = str(vec(1, 2) + vec(3, 4) * 2);
+            var shown = str(vec(5, 5) - vec(1, 2)) + \" costs \" + money(250);
+            var same = [vec(1, 2) == vec(1, 2), vec(1, 2) != vec(1, 2), vec(1, 2) == vec(2, 1)];
+            var a = money(100); var b = money(250);
+            var order = [a < b, a > b, a <= a, b >= a, b <= a];
+            var f = fun () {};
+            var identity = [f == f, f == fun () {}, keys == keys];");
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "sum"), s("<7, 10>"));
+        assert_eq!(global(&interpreter, "shown"), s("<4, 3> costs $2.5"));
+        let booleans = |values: &[bool]| {
+            LoxObject::list(values.iter().map(|b| LoxObject::Boolean(*b)).collect())
+        };
+        assert_eq!(
+            global(&interpreter, "same"),
+            booleans(&[true, false, false])
+        );
+        assert_eq!(
+            global(&interpreter, "order"),
+            booleans(&[true, false, true, true, false])
+        );
+        assert_eq!(
+            global(&interpreter, "identity"),
+            booleans(&[true, false, true])
+        );
+
+        let (_, result) = run("var bad = {__add: () => 1}; var r = bad + 1;");
+        assert!(matches!(result, Err(RuntimeError::ArityMismatch(..))));
+    }
+
+    #[test]
+    fn test_closure_capture() {
+        let (interpreter, result) = run("var fns = [nil, nil, nil];
+            for (var i = 0; i < 3; i = i + 1) fns[i] = () => i;
+            var seen = [fns[0](), fns[1](), fns[2]()];
+
+            fun counter() {
+                var count = 0;
+                return [() => count = count + 1, () => count];
+            }
+            var [inc, get] = counter();
+            inc(); inc();
+            var count = get();
+
+            var late = [nil, nil];
+            for (var j = 0; j < 2;) { late[j] = () => j; j = j + 1; }
+            var late_seen = [late[0](), late[1]()];
+
+            fun outer() {
+                fun fact(n) { return n < 2 ? 1 : n * fact(n - 1); }
+                return fact(5) + later();
+            }
+            fun later() { return 1; }
+            var total = outer();");
+        assert_eq!(result, Ok(()));
+        let integers = |values: &[i64]| {
+            LoxObject::list(values.iter().map(|i| LoxObject::Integer(*i)).collect())
+        };
+        assert_eq!(global(&interpreter, "seen"), integers(&[0, 1, 2]));
+        assert_eq!(global(&interpreter, "count"), LoxObject::Integer(2));
+        assert_eq!(global(&interpreter, "late_seen"), integers(&[1, 2]));
+        assert_eq!(global(&interpreter, "total"), LoxObject::Integer(121));
+    }
+
+    #[test]
+    fn test_local_functions_see_later_siblings() {
+        let (interpreter, result) = run("var forward = nil;
+            { fun f() { return g(); } fun g() { return 1; } forward = f(); }
+
+            fun outer() {
+                fun is_even(n) { return n == 0 ? true : is_odd(n - 1); }
+                fun is_odd(n) { return n == 0 ? false : is_even(n - 1); }
+                return [is_even(10), is_odd(7), !is_even(3)];
+            }
+            var parity = outer();");
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "forward"), LoxObject::Integer(1));
+        assert_eq!(
+            global(&interpreter, "parity"),
+            LoxObject::list(vec![LoxObject::Boolean(true); 3])
+        );
+    }
+
+    #[test]
+    fn test_captures_through_nested_functions() {
+        let (interpreter, result) = run("fun outer() {
+                var a = 1;
+                // middle never uses a, but has to pass it on to inner.
+                fun middle() {
+                    var b = 2;
+                    return () => { a = a + 10; return a + b; };
+                }
+                return middle();
+            }
+            var f = outer();
+            var results = [f(), f()];
+
+            var name = \"global\";
+            var shadowed = nil;
+            {
+                var get = () => name;
+                var name = \"local\";
+                { var name = \"inner\"; shadowed = [get(), name]; }
+            }");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            global(&interpreter, "results"),
+            LoxObject::list(vec![LoxObject::Integer(13), LoxObject::Integer(23)])
+        );
+        assert_eq!(
+            global(&interpreter, "shadowed"),
+            LoxObject::list(vec![s("global"), s("inner")])
+        );
+    }
+
+    #[test]
+    fn test_numeric_and_bitwise_operators() {
+        let (interpreter, result) = run("var r = [-7 % 3, 2 ** 3 ** 2, -2 ** 2, -7 ~/ 2,
+            6 & 3, 6 | 3, 6 ^ 3, ~5, 1 << 10, -16 >> 2, 5 & 1 == 1];
+            var x = 10; x ~/= 3; x **= 2; x %= 5; x <<= 2;");
+        assert_eq!(result, Ok(()));
+        let i = LoxObject::Integer;
+        let r = global(&interpreter, "r");
+        assert_eq!(
+            r,
+            LoxObject::list(vec![
+                i(2),
+                i(512),
+                i(-4),
+                i(-4),
+                i(2),
+                i(7),
+                i(5),
+                i(-6),
+                i(1024),
+                i(-4),
+                LoxObject::Boolean(true),
+            ])
+        );
+        // equality compares integers and floats by value, so check the types too.
+        if let LoxObject::List(values) = r {
+            let types: Vec<&str> = values.borrow().iter().map(|v| v.type_name()).collect();
+            let mut expected = vec!["integer"; 10];
+            expected.push("boolean");
+            assert_eq!(types, expected);
+        }
+        let x = global(&interpreter, "x");
+        assert_eq!(x, i(16));
+        assert_eq!(x.type_name(), "integer");
+
+        for input in ["var a = 1.5 & 1;", "var a = 1 << 64;", "var a = ~\"x\";"] {
+            let (_, result) = run(input);
+            assert!(result.is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_trailing_comments_after_operands() {
+        let (interpreter, result) = run("var x = 1; var seen = nil;
+            if (x > 0) // positive
+                seen = x;
+            fun f() // no body yet
+            { return 2; }
+            var a = 7 // 2;
+            ;
+            var b = f() // called
+            ;");
+        assert_eq!(result, Ok(()));
+        assert_eq!(global(&interpreter, "seen"), LoxObject::Integer(1));
+        assert_eq!(global(&interpreter, "a"), LoxObject::Integer(7));
+        assert_eq!(global(&interpreter, "b"), LoxObject::Integer(2));
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let (interpreter, result) = run("var big = 9007199254740993 + 2;
+            var r = [7 / 2, 7 ~/ 2, -7 % 3, 2 ** 62, 1 + 0.5, 3 * 1.0, 1 == 1.0, 2 > 1.5];
+            var shown = str(3) + \" \" + str(3.5);");
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            global(&interpreter, "big"),
+            LoxObject::Integer(9007199254740995)
+        );
+        let r = global(&interpreter, "r");
+        assert_eq!(
+            r.to_string(),
+            "[3.5, 3, 2, 4611686018427387904, 1.5, 3, true, true]"
+        );
+        if let LoxObject::List(values) = r {
+            let types: Vec<&str> = values.borrow().iter().map(|v| v.type_name()).collect();
+            assert_eq!(
+                types,
+                vec![
+                    "number", "integer", "integer", "integer", "number", "number", "boolean",
+                    "boolean"
+                ]
+            );
+        }
+        assert_eq!(global(&interpreter, "shown"), s("3 3.5"));
+
+        for input in [
+            "var a = 9223372036854775807 + 1;",
+            "var a = 2 ** 63;",
+            "var a = 1 ~/ 0;",
+            "var a = 1 % 0;",
+        ] {
+            let (_, result) = run(input);
+            assert!(
+                matches!(result, Err(RuntimeError::ArithmeticError(..))),
+                "{}",
+                input
+            );
+        }
+    }
+}
@@ -1,4 +1,165 @@
-use crate::language::token::Token;
+use crate::language::token::{Literal, Slot, Token};
+use std::rc::Rc;
+
+// a type written after a name ("var x: number") or a parameter list, with a
+// trailing "?" also allowing nil. Only "rlox --check" reads them, running a
+// program ignores them completely.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeAnnotation {
+    pub name: Token,
+    pub nullable: bool,
+}
+
+// a function parameter, "b = 10" has a default (evaluated at call time) while
+// "...rest" collects the remaining arguments into a list and is always last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Token,
+    pub annotation: Option<TypeAnnotation>,
+    pub default: Option<Expr>,
+    pub is_rest: bool,
+}
+
+impl Param {
+    pub fn new(name: Token) -> Self {
+        Self {
+            name,
+            annotation: None,
+            default: None,
+            is_rest: false,
+        }
+    }
+}
+
+// the key named by a map literal or pattern, either a string or a bare identifier.
+pub fn map_key(key: &Token) -> String {
+    match &key.literal {
+        Literal::String(s) => s.clone(),
+        _ => key.lexeme_or_empty(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    // "_" matches anything without binding it.
+    Wildcard(Token),
+    // a number, string, boolean or nil literal, compared with "==".
+    Literal(Token),
+    // a bare name matches anything and binds it for the guard and body.
+    Binding(Token),
+    // "a" | "b", alternatives can't bind names since only one of them matches.
+    Alternatives(Vec<Pattern>),
+    // [first, second, ...rest] matches lists of exactly (or with a rest, at
+    // least) that many elements.
+    List {
+        bracket: Token,
+        elements: Vec<Pattern>,
+        rest: Option<Token>,
+    },
+    // {key: pattern, name} matches maps having at least those keys, a bare
+    // name is short for "name: name".
+    Map {
+        brace: Token,
+        entries: Vec<(Token, Pattern)>,
+    },
+}
+
+impl Pattern {
+    // the token to report the pattern at.
+    pub fn token(&self) -> &Token {
+        match self {
+            Pattern::Wildcard(tok) | Pattern::Literal(tok) | Pattern::Binding(tok) => tok,
+            Pattern::Alternatives(alternatives) => alternatives[0].token(),
+            Pattern::List { bracket, .. } => bracket,
+            Pattern::Map { brace, .. } => brace,
+        }
+    }
+
+    // every name the pattern binds, in order.
+    pub fn bindings(&self) -> Vec<&Token> {
+        match self {
+            Pattern::Wildcard(_) | Pattern::Literal(_) => vec![],
+            Pattern::Binding(name) => vec![name],
+            Pattern::Alternatives(alternatives) => {
+                alternatives.iter().flat_map(Pattern::bindings).collect()
+            }
+            Pattern::List { elements, rest, .. } => elements
+                .iter()
+                .flat_map(Pattern::bindings)
+                .chain(rest.iter())
+                .collect(),
+            Pattern::Map { entries, .. } => {
+                entries.iter().flat_map(|(_, p)| p.bindings()).collect()
+            }
+        }
+    }
+
+    pub fn bindings_mut(&mut self) -> Vec<&mut Token> {
+        match self {
+            Pattern::Wildcard(_) | Pattern::Literal(_) => vec![],
+            Pattern::Binding(name) => vec![name],
+            Pattern::Alternatives(alternatives) => alternatives
+                .iter_mut()
+                .flat_map(Pattern::bindings_mut)
+                .collect(),
+            Pattern::List { elements, rest, .. } => elements
+                .iter_mut()
+                .flat_map(Pattern::bindings_mut)
+                .chain(rest.iter_mut())
+                .collect(),
+            Pattern::Map { entries, .. } => entries
+                .iter_mut()
+                .flat_map(|(_, p)| p.bindings_mut())
+                .collect(),
+        }
+    }
+
+    // whether every value matched by other is also matched by this pattern,
+    // this is conservative: false doesn't mean other can match something new.
+    pub fn covers(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (Pattern::Wildcard(_) | Pattern::Binding(_), _) => true,
+            (_, Pattern::Alternatives(others)) => others.iter().all(|o| self.covers(o)),
+            (Pattern::Alternatives(alternatives), _) => {
+                alternatives.iter().any(|a| a.covers(other))
+            }
+            (Pattern::Literal(a), Pattern::Literal(b)) => a.literal == b.literal,
+            (
+                Pattern::List {
+                    elements: a,
+                    rest: a_rest,
+                    ..
+                },
+                Pattern::List {
+                    elements: b,
+                    rest: b_rest,
+                    ..
+                },
+            ) => {
+                let lengths = match a_rest {
+                    Some(_) => a.len() <= b.len(),
+                    None => b_rest.is_none() && a.len() == b.len(),
+                };
+                lengths && a.iter().zip(b).all(|(a, b)| a.covers(b))
+            }
+            (Pattern::Map { entries: a, .. }, Pattern::Map { entries: b, .. }) => {
+                a.iter().all(|(key, a)| {
+                    b.iter()
+                        .any(|(other, b)| map_key(key) == map_key(other) && a.covers(b))
+                })
+            }
+            _ => false,
+        }
+    }
+}
+
+// pattern [if guard] => body
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+}
 
 macro_rules! define_ast {
     ($enum_name:ident $visitor_name:ident {
@@ -17,15 +178,17 @@ macro_rules! define_ast {
             pub fn accept<T>(&self, visitor: &mut dyn $visitor_name<T>) -> T {
                 match self {
                     $(
-                        $enum_name::$variant_name { $($field_name),* } => visitor.$visitor_method($($field_name.clone()),*),
+                        $enum_name::$variant_name { $($field_name),* } => visitor.$visitor_method($($field_name),*),
                     )*
                 }
             }
         }
 
+        // visitors borrow the fields of the node they're visiting as is.
+        #[allow(clippy::ptr_arg, clippy::borrowed_box)]
         pub trait $visitor_name<T> {
             $(
-                fn $visitor_method(&mut self, $($field_name: $field_type),*) -> T;
+                fn $visitor_method(&mut self, $($field_name: &$field_type),*) -> T;
             )*
         }
     };
@@ -62,22 +225,93 @@ define_ast! {
             name: Token,
         },
 
+        // [a, b] = [b, a], every name in the pattern must already be declared.
+        DestructureAssign visit_destructure_assign {
+            pattern: Box<Pattern>,
+            equals: Token,
+            value: Box<Expr>,
+        },
+
         Assign visit_assign {
             name: Token,
             value: Box<Expr>,
         },
 
+        // cond ? then_branch : else_branch, only the chosen branch is evaluated.
+        Ternary visit_ternary {
+            condition: Box<Expr>,
+            then_branch: Box<Expr>,
+            else_branch: Box<Expr>,
+        },
+
+        // match (subject) { pattern if guard => body, ... } evaluates the body
+        // of the first arm whose pattern matches and whose guard holds.
+        Match visit_match {
+            keyword: Token,
+            subject: Box<Expr>,
+            arms: Vec<MatchArm>,
+        },
+
+        // the and, or and ?? (nil coalescing) operators, all short circuiting.
         Logical visit_logical {
             left: Box<Expr>,
             operator: Token,
             right: Box<Expr>,
         },
 
+        List visit_list {
+            bracket: Token,
+            elements: Vec<Expr>,
+        },
+
+        Index visit_index {
+            object: Box<Expr>,
+            bracket: Token,
+            index: Box<Expr>,
+        },
+
+        SetIndex visit_set_index {
+            object: Box<Expr>,
+            bracket: Token,
+            index: Box<Expr>,
+            value: Box<Expr>,
+        },
+
+        Get visit_get {
+            object: Box<Expr>,
+            name: Token,
+        },
+
+        // object?.name evaluates to nil when the object is nil.
+        SafeGet visit_safe_get {
+            object: Box<Expr>,
+            name: Token,
+        },
+
+        Set visit_set {
+            object: Box<Expr>,
+            name: Token,
+            value: Box<Expr>,
+        },
+
+        // keys are either string literals or bare identifiers.
+        Map visit_map {
+            brace: Token,
+            entries: Vec<(Token, Expr)>,
+        },
+
         // to support anonymous functions, we create a function node,
         // so that functions can produce a value in place of creating a side effect.
+        // captures lists the variables the function closes over, as the
+        // resolver found them from where the function is created. The body is
+        // shared with every function value created from it. is_generator is
+        // whether the body contains a "yield", worked out once by the parser.
         Function visit_function {
-            params: Vec<Token>,
-            body: Vec<Stmt>
+            params: Vec<Param>,
+            return_type: Option<TypeAnnotation>,
+            body: Rc<[Stmt]>,
+            captures: Vec<Slot>,
+            is_generator: bool,
         },
     }
 }
@@ -94,9 +328,24 @@ define_ast! {
 
         Var visit_var {
             name: Token,
+            annotation: Option<TypeAnnotation>,
             initializer: Option<Expr>,
         },
 
+        // var [a, b] = pair; or var {name, age} = person;
+        Destructure visit_destructure {
+            keyword: Token,
+            pattern: Box<Pattern>,
+            initializer: Expr,
+        },
+
+        // unlike var, a const must be initialized and can't be reassigned.
+        Const visit_const {
+            name: Token,
+            annotation: Option<TypeAnnotation>,
+            initializer: Expr,
+        },
+
         Block visit_block {
             statements: Vec<Stmt>,
         },
@@ -112,6 +361,23 @@ define_ast! {
             body: Box<Stmt>,
         },
 
+        // for (initializer; condition; increment) body, where the variables
+        // declared by the initializer get a fresh binding for every iteration.
+        For visit_for {
+            initializer: Option<Box<Stmt>>,
+            condition: Expr,
+            increment: Option<Expr>,
+            body: Box<Stmt>,
+        },
+
+        // for (var name in iterable) body, see LoxIterator for what's iterable.
+        ForIn visit_for_in {
+            keyword: Token,
+            name: Token,
+            iterable: Expr,
+            body: Box<Stmt>,
+        },
+
         // the only difference here is that break returns a value (true);
         // every other statement should return null (for now...)
         Break visit_break {
@@ -120,13 +386,112 @@ define_ast! {
 
         Function visit_function {
             name: Token,
-            params: Vec<Token>,
-            body: Vec<Stmt>
+            params: Vec<Param>,
+            return_type: Option<TypeAnnotation>,
+            body: Rc<[Stmt]>,
+            captures: Vec<Slot>,
+            is_generator: bool,
+        },
+
+        // hands a value to whoever resumes the generator this runs in, with
+        // "var target = yield value;" declaring what it's next resumed with.
+        Yield visit_yield {
+            keyword: Token,
+            value: Option<Expr>,
+            target: Option<Token>,
+            annotation: Option<TypeAnnotation>,
         },
 
         Return visit_return {
             keyword: Token,
             value: Option<Expr>,
+        },
+
+        Throw visit_throw {
+            keyword: Token,
+            value: Expr,
+        },
+
+        // "import path as alias;" binds the whole module, while
+        // "from path import a, b;" binds the listed names (alias is None).
+        Import visit_import {
+            keyword: Token,
+            path: Token,
+            alias: Option<Token>,
+            names: Vec<Token>,
+        },
+
+        // at least one of the catch or finally clauses is present.
+        Try visit_try {
+            keyword: Token,
+            body: Vec<Stmt>,
+            catch_name: Option<Token>,
+            catch_body: Option<Vec<Stmt>>,
+            finally_body: Option<Vec<Stmt>>,
+        }
+    }
+}
+
+impl Stmt {
+    // the name introduced by a var, const or fun declaration.
+    pub fn declared_name(&self) -> Option<&Token> {
+        match self {
+            Stmt::Var { name, .. } | Stmt::Const { name, .. } | Stmt::Function { name, .. } => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn declared_name_mut(&mut self) -> Option<&mut Token> {
+        match self {
+            Stmt::Var { name, .. } | Stmt::Const { name, .. } | Stmt::Function { name, .. } => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    // whether running this statement can suspend the enclosing function, which
+    // makes that function a generator. Nested functions don't count.
+    pub fn contains_yield(&self) -> bool {
+        match self {
+            Stmt::Yield { .. } => true,
+            Stmt::Block { statements } => statements.iter().any(Stmt::contains_yield),
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                then_branch.contains_yield()
+                    || else_branch.as_ref().is_some_and(|s| s.contains_yield())
+            }
+            Stmt::While { body, .. } | Stmt::ForIn { body, .. } => body.contains_yield(),
+            Stmt::For {
+                initializer, body, ..
+            } => initializer.as_ref().is_some_and(|s| s.contains_yield()) || body.contains_yield(),
+            Stmt::Try {
+                body,
+                catch_body,
+                finally_body,
+                ..
+            } => body
+                .iter()
+                .chain(catch_body.iter().flatten())
+                .chain(finally_body.iter().flatten())
+                .any(Stmt::contains_yield),
+            _ => false,
+        }
+    }
+
+    // the "///" documentation written above a declaration.
+    pub fn doc(&self) -> Option<&str> {
+        self.declared_name().and_then(|name| name.doc.as_deref())
+    }
+
+    pub fn set_doc(&mut self, doc: String) {
+        if let Some(name) = self.declared_name_mut() {
+            name.doc = Some(doc);
         }
     }
 }
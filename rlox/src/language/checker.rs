@@ -0,0 +1,806 @@
+use crate::language::ast::{Expr, MatchArm, Param, Stmt, TypeAnnotation};
+use crate::language::errors::ParseError;
+use crate::language::token::{Literal, Token, TokenType};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// the types "rlox --check" reasons about, named like the runtime's type_name.
+// Whatever it can't pin down is Any, which fits everywhere, so unannotated
+// code stays dynamically typed and is only checked where the types are obvious.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Nil,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    List,
+    Map,
+    Channel,
+    // the signature is known for functions declared in the checked source.
+    Function(Option<Rc<Signature>>),
+    // "type?", the type or nil.
+    Optional(Box<Type>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Signature {
+    // the types of the parameters before any rest parameter.
+    params: Vec<Type>,
+    // how many of them have no default.
+    required: usize,
+    has_rest: bool,
+    returns: Type,
+}
+
+impl Type {
+    fn from_annotation(annotation: &TypeAnnotation) -> Result<Type, String> {
+        let name = annotation.name.lexeme_or_empty();
+        let t = match name.as_str() {
+            "any" => Type::Any,
+            "nil" => Type::Nil,
+            "boolean" => Type::Boolean,
+            "integer" => Type::Integer,
+            "number" => Type::Number,
+            "string" => Type::String,
+            "list" => Type::List,
+            "map" => Type::Map,
+            "channel" => Type::Channel,
+            "function" => Type::Function(None),
+            _ => return Err(name),
+        };
+        Ok(if annotation.nullable { t.nullable() } else { t })
+    }
+
+    fn nullable(self) -> Type {
+        match self {
+            Type::Any | Type::Nil | Type::Optional(_) => self,
+            t => Type::Optional(Box::new(t)),
+        }
+    }
+
+    // whether a value of type other can be stored where this type is expected.
+    fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Number, Type::Integer) => true,
+            // signatures aren't compared, any function will do.
+            (Type::Function(_), Type::Function(_)) => true,
+            (Type::Optional(_), Type::Nil) => true,
+            (Type::Optional(t), Type::Optional(other)) => t.accepts(other),
+            (Type::Optional(t), other) => t.accepts(other),
+            _ => self == other,
+        }
+    }
+
+    // the type of a value that's either of the two.
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Type::Integer, Type::Number) | (Type::Number, Type::Integer) => Type::Number,
+            (Type::Nil, t) | (t, Type::Nil) => t.nullable(),
+            (Type::Optional(a), b) | (b, Type::Optional(a)) => a.join(b).nullable(),
+            (Type::Function(_), Type::Function(_)) => Type::Function(None),
+            _ => Type::Any,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Integer | Type::Number)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Nil => write!(f, "nil"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Integer => write!(f, "integer"),
+            Type::Number => write!(f, "number"),
+            Type::String => write!(f, "string"),
+            Type::List => write!(f, "list"),
+            Type::Map => write!(f, "map"),
+            Type::Channel => write!(f, "channel"),
+            Type::Function(_) => write!(f, "function"),
+            Type::Optional(t) => write!(f, "{}?", t),
+        }
+    }
+}
+
+// the signature of a function, unknown type names are reported when its
+// parameters are checked so here they're just Any.
+fn signature(
+    params: &[Param],
+    return_type: &Option<TypeAnnotation>,
+    is_generator: bool,
+) -> Signature {
+    let annotated = |annotation: &Option<TypeAnnotation>| {
+        annotation
+            .as_ref()
+            .and_then(|a| Type::from_annotation(a).ok())
+            .unwrap_or(Type::Any)
+    };
+    let fixed: Vec<&Param> = params.iter().filter(|p| !p.is_rest).collect();
+
+    Signature {
+        params: fixed.iter().map(|p| annotated(&p.annotation)).collect(),
+        required: fixed.iter().filter(|p| p.default.is_none()).count(),
+        has_rest: params.iter().any(|p| p.is_rest),
+        // calling a generator function hands back the generator object.
+        returns: if is_generator {
+            Type::Map
+        } else {
+            annotated(return_type)
+        },
+    }
+}
+
+// "rlox --check", a static pass that infers the types of expressions and
+// verifies them against the annotations on variables, parameters and return
+// values. Operators follow the same rules as apply_binary, so a reported
+// mismatch is an error the program would otherwise hit at runtime.
+#[derive(Debug, Default)]
+pub struct Checker {
+    scopes: Vec<HashMap<String, Type>>,
+    errors: Vec<ParseError>,
+    // the declared return type of the function being checked, if any.
+    returns: Option<Type>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            ..Self::default()
+        }
+    }
+
+    pub fn check(mut self, stmts: &[Stmt]) -> Result<(), Vec<ParseError>> {
+        self.statements(stmts);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn error(&mut self, tok: &Token, msg: String) {
+        self.errors.push(ParseError::TypeMismatch {
+            msg,
+            coordinate: tok.coordinate.clone(),
+        });
+    }
+
+    fn expect(&mut self, expected: &Type, found: &Type, tok: &Token, context: &str) {
+        if !expected.accepts(found) {
+            let msg = format!("Expected {} for {} but got {}", expected, context, found);
+            self.error(tok, msg);
+        }
+    }
+
+    // the declared type, where no annotation means Any.
+    fn annotated(&mut self, annotation: &Option<TypeAnnotation>) -> Type {
+        let Some(annotation) = annotation else {
+            return Type::Any;
+        };
+        Type::from_annotation(annotation).unwrap_or_else(|name| {
+            self.error(&annotation.name, format!("Unknown type \"{}\"", name));
+            Type::Any
+        })
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token, t: Type) {
+        let scope = self.scopes.last_mut().expect("checker always has a scope");
+        scope.insert(name.lexeme_or_empty(), t);
+    }
+
+    // names the checker hasn't seen (natives, globals from elsewhere) are Any.
+    fn lookup(&self, name: &Token) -> Type {
+        let lexeme = name.lexeme_or_empty();
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(&lexeme))
+            .cloned()
+            .unwrap_or(Type::Any)
+    }
+
+    fn statements(&mut self, stmts: &[Stmt]) {
+        // a function can be called from code above its declaration (inside
+        // another function), so signatures are known for the whole block.
+        for stmt in stmts {
+            if let Stmt::Function {
+                name,
+                params,
+                return_type,
+                is_generator,
+                ..
+            } = stmt
+            {
+                let signature = signature(params, return_type, *is_generator);
+                self.declare(name, Type::Function(Some(Rc::new(signature))));
+            }
+        }
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn function(
+        &mut self,
+        params: &[Param],
+        return_type: &Option<TypeAnnotation>,
+        body: &[Stmt],
+        is_generator: bool,
+    ) -> Type {
+        let signature = Rc::new(signature(params, return_type, is_generator));
+        // a generator's return value ends up in a {value, done} map.
+        let returns = match is_generator {
+            true => None,
+            false => Some(self.annotated(return_type)),
+        };
+        let enclosing = std::mem::replace(&mut self.returns, returns);
+
+        self.scoped(|c| {
+            for param in params {
+                let declared = c.annotated(&param.annotation);
+                if let Some(default) = &param.default {
+                    let found = c.expression(default);
+                    let context = format!("the default of \"{}\"", param.name.lexeme_or_empty());
+                    c.expect(&declared, &found, &param.name, &context);
+                }
+                match (param.is_rest, &param.annotation) {
+                    (true, None) => c.declare(&param.name, Type::List),
+                    _ => c.declare(&param.name, declared),
+                }
+            }
+            c.statements(body);
+        });
+
+        self.returns = enclosing;
+        Type::Function(Some(signature))
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expression } | Stmt::Print { expression } => {
+                self.expression(expression);
+            }
+            Stmt::Var {
+                name,
+                annotation,
+                initializer,
+            } => {
+                let declared = self.annotated(annotation);
+                if let Some(initializer) = initializer {
+                    let found = self.expression(initializer);
+                    let context = format!("\"{}\"", name.lexeme_or_empty());
+                    self.expect(&declared, &found, name, &context);
+                }
+                self.declare(name, declared);
+            }
+            Stmt::Const {
+                name,
+                annotation,
+                initializer,
+            } => {
+                let found = self.expression(initializer);
+                // a const never changes, so without an annotation it keeps
+                // the type of its initializer.
+                let declared = match annotation {
+                    Some(_) => self.annotated(annotation),
+                    None => found.clone(),
+                };
+                let context = format!("\"{}\"", name.lexeme_or_empty());
+                self.expect(&declared, &found, name, &context);
+                self.declare(name, declared);
+            }
+            Stmt::Destructure {
+                pattern,
+                initializer,
+                ..
+            } => {
+                self.expression(initializer);
+                for name in pattern.bindings() {
+                    self.declare(name, Type::Any);
+                }
+            }
+            Stmt::Block { statements } => self.scoped(|c| c.statements(statements)),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => self.scoped(|c| {
+                if let Some(initializer) = initializer {
+                    c.statement(initializer);
+                }
+                c.expression(condition);
+                if let Some(increment) = increment {
+                    c.expression(increment);
+                }
+                c.statement(body);
+            }),
+            Stmt::ForIn {
+                keyword,
+                name,
+                iterable,
+                body,
+            } => {
+                let element = match self.expression(iterable) {
+                    Type::String => Type::String,
+                    Type::List | Type::Map | Type::Any => Type::Any,
+                    other => {
+                        self.error(keyword, format!("Cannot iterate over {}", other));
+                        Type::Any
+                    }
+                };
+                self.scoped(|c| {
+                    c.declare(name, element);
+                    c.statement(body);
+                });
+            }
+            Stmt::Break { .. } => {}
+            Stmt::Function {
+                name,
+                params,
+                return_type,
+                body,
+                is_generator,
+                ..
+            } => {
+                let function = self.function(params, return_type, body, *is_generator);
+                self.declare(name, function);
+            }
+            Stmt::Return { keyword, value } => {
+                let found = match value {
+                    Some(value) => self.expression(value),
+                    None => Type::Nil,
+                };
+                if let Some(expected) = self.returns.clone() {
+                    self.expect(&expected, &found, keyword, "the return value");
+                }
+            }
+            Stmt::Yield {
+                value,
+                target,
+                annotation,
+                ..
+            } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                // what the generator is resumed with isn't known statically, so
+                // the annotation is trusted, like a parameter's.
+                let declared = self.annotated(annotation);
+                if let Some(target) = target {
+                    self.declare(target, declared);
+                }
+            }
+            Stmt::Throw { value, .. } => {
+                self.expression(value);
+            }
+            Stmt::Import { alias, names, .. } => {
+                for name in alias.iter().chain(names) {
+                    self.declare(name, Type::Any);
+                }
+            }
+            Stmt::Try {
+                body,
+                catch_name,
+                catch_body,
+                finally_body,
+                ..
+            } => {
+                self.scoped(|c| c.statements(body));
+                if let Some(catch_body) = catch_body {
+                    self.scoped(|c| {
+                        if let Some(name) = catch_name {
+                            c.declare(name, Type::Any);
+                        }
+                        c.statements(catch_body);
+                    });
+                }
+                if let Some(finally_body) = finally_body {
+                    self.scoped(|c| c.statements(finally_body));
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal { value } => match value.literal {
+                Literal::Integer(_) => Type::Integer,
+                Literal::Number(_) => Type::Number,
+                Literal::String(_) => Type::String,
+                Literal::Boolean(_) => Type::Boolean,
+                Literal::Nil => Type::Nil,
+            },
+            Expr::Grouping { expression } => self.expression(expression),
+            Expr::Variable { name } => self.lookup(name),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.expression(left);
+                let right = self.expression(right);
+                self.binary(left, operator, right)
+            }
+            Expr::Unary { operator, right } => {
+                let right = self.expression(right);
+                self.unary(operator, right)
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.expression(left);
+                let right = self.expression(right);
+                match (operator.token_type, left) {
+                    (TokenType::QuestionQuestion, Type::Nil) => right,
+                    (TokenType::QuestionQuestion, Type::Optional(left)) => left.join(right),
+                    (_, left) => left.join(right),
+                }
+            }
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                let then_branch = self.expression(then_branch);
+                let else_branch = self.expression(else_branch);
+                then_branch.join(else_branch)
+            }
+            Expr::Match { subject, arms, .. } => {
+                self.expression(subject);
+                self.match_arms(arms)
+            }
+            Expr::Call {
+                callee,
+                paren,
+                args,
+            } => {
+                let callee = self.expression(callee);
+                let args: Vec<Type> = args.iter().map(|arg| self.expression(arg)).collect();
+                self.call(callee, paren, &args)
+            }
+            Expr::Assign { name, value } => {
+                let found = self.expression(value);
+                let declared = self.lookup(name);
+                let context = format!("\"{}\"", name.lexeme_or_empty());
+                self.expect(&declared, &found, name, &context);
+                found
+            }
+            Expr::DestructureAssign { value, .. } => self.expression(value),
+            Expr::List { elements, .. } => {
+                elements.iter().for_each(|e| {
+                    self.expression(e);
+                });
+                Type::List
+            }
+            Expr::Map { entries, .. } => {
+                entries.iter().for_each(|(_, e)| {
+                    self.expression(e);
+                });
+                Type::Map
+            }
+            Expr::Index { object, index, .. } => {
+                let object = self.expression(object);
+                self.expression(index);
+                match object {
+                    Type::String => Type::String,
+                    _ => Type::Any,
+                }
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value)
+            }
+            Expr::Get { object, name } => {
+                match self.expression(object) {
+                    Type::Map | Type::Any => {}
+                    other => {
+                        let msg = format!(
+                            "Cannot read property \"{}\" of {}",
+                            name.lexeme_or_empty(),
+                            other
+                        );
+                        self.error(name, msg);
+                    }
+                }
+                Type::Any
+            }
+            Expr::SafeGet { object, .. } => {
+                self.expression(object);
+                Type::Any
+            }
+            Expr::Set { object, value, .. } => {
+                self.expression(object);
+                self.expression(value)
+            }
+            Expr::Function {
+                params,
+                return_type,
+                body,
+                is_generator,
+                ..
+            } => self.function(params, return_type, body, *is_generator),
+        }
+    }
+
+    fn match_arms(&mut self, arms: &[MatchArm]) -> Type {
+        let mut result: Option<Type> = None;
+        for arm in arms {
+            self.scoped(|c| {
+                for name in arm.pattern.bindings() {
+                    c.declare(name, Type::Any);
+                }
+                if let Some(guard) = &arm.guard {
+                    c.expression(guard);
+                }
+                let body = c.expression(&arm.body);
+                result = Some(match result.take() {
+                    Some(t) => t.join(body),
+                    None => body,
+                });
+            });
+        }
+        result.unwrap_or(Type::Any)
+    }
+
+    fn call(&mut self, callee: Type, paren: &Token, args: &[Type]) -> Type {
+        let signature = match callee {
+            Type::Function(Some(signature)) => signature,
+            Type::Function(None) | Type::Any => return Type::Any,
+            other => {
+                self.error(paren, format!("Cannot call {}", other));
+                return Type::Any;
+            }
+        };
+
+        let (min, max) = (signature.required, signature.params.len());
+        if args.len() < min || (!signature.has_rest && args.len() > max) {
+            let expected = match (signature.has_rest, min == max) {
+                (true, _) => format!("at least {}", min),
+                (false, true) => min.to_string(),
+                (false, false) => format!("{} to {}", min, max),
+            };
+            let msg = format!("Expected {} arguments but got {}", expected, args.len());
+            self.error(paren, msg);
+        }
+        for (i, (expected, found)) in signature.params.iter().zip(args).enumerate() {
+            let context = format!("argument {}", i + 1);
+            self.expect(expected, found, paren, &context);
+        }
+        signature.returns.clone()
+    }
+
+    fn binary(&mut self, left: Type, operator: &Token, right: Type) -> Type {
+        if matches!(
+            operator.token_type,
+            TokenType::EqualEqual | TokenType::BangEqual
+        ) {
+            return Type::Boolean;
+        }
+        // maps can overload operators, see LoxVisitor::apply_overload.
+        if matches!(left, Type::Any | Type::Map) || matches!(right, Type::Any | Type::Map) {
+            return Type::Any;
+        }
+
+        let numeric = left.is_numeric() && right.is_numeric();
+        let integers = left == Type::Integer && right == Type::Integer;
+        let result = match operator.token_type {
+            TokenType::Plus if left == Type::String || right == Type::String => Some(Type::String),
+            TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Percent
+            | TokenType::TildeSlash => numeric.then_some(match integers {
+                true => Type::Integer,
+                false => Type::Number,
+            }),
+            TokenType::Slash => numeric.then_some(Type::Number),
+            // an integer to a negative power is a float.
+            TokenType::StarStar => numeric.then_some(match integers {
+                true => Type::Any,
+                false => Type::Number,
+            }),
+            TokenType::Ampersand
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::LessLess
+            | TokenType::GreaterGreater => numeric.then_some(Type::Integer),
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => numeric.then_some(Type::Boolean),
+            _ => Some(Type::Any),
+        };
+
+        result.unwrap_or_else(|| {
+            let msg = format!(
+                "Operator \"{}\" cannot be applied to {} and {}",
+                operator.lexeme_or_empty(),
+                left,
+                right
+            );
+            self.error(operator, msg);
+            Type::Any
+        })
+    }
+
+    fn unary(&mut self, operator: &Token, right: Type) -> Type {
+        let result = match operator.token_type {
+            TokenType::Bang => return Type::Boolean,
+            _ if right == Type::Any => return Type::Any,
+            TokenType::Minus => right.is_numeric().then_some(right.clone()),
+            TokenType::Tilde => right.is_numeric().then_some(Type::Integer),
+            _ => return Type::Any,
+        };
+
+        result.unwrap_or_else(|| {
+            let msg = format!(
+                "Operator \"{}\" cannot be applied to {}",
+                operator.lexeme_or_empty(),
+                right
+            );
+            self.error(operator, msg);
+            Type::Any
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::parse;
+
+    fn check(input: &str) -> Result<(), Vec<String>> {
+        let ast = parse(input).unwrap();
+        Checker::new()
+            .check(&ast)
+            .map_err(|errors| errors.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[test]
+    fn test_unannotated_code_is_dynamic() {
+        let result = check(
+            "var x = 1; x = \"now a string\";
+            fun add(a, b) { return a + b; }
+            var y = add(1, 2) - add(\"a\", \"b\");
+            var m = {n: 1}; print m.n * 2 + len(\"abc\");
+            var v = {__add: (a, b) => 1}; print v + 1;",
+        );
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_annotations_are_checked() {
+        let result = check(
+            "fun add(a: number, b: number): number { return a + b; }
+            var n: number = add(1, 2.5);
+            var i: integer = 2 * 3 ~/ 2;
+            var s: string = \"total \" + n;
+            var maybe: string? = nil;
+            const c = 1;
+            var f: function = add;
+            fun count(...items): integer { return len(items); }
+            fun gen(): map { yield 1; return \"done\"; }
+            var jobs: channel = chan();
+            fun sum() { var total = 0; while (true) { var n: number = yield total; total = total + n; } }
+            for (var ch in s) { var t: string = ch; }",
+        );
+        assert_eq!(result, Ok(()));
+
+        for (input, expected) in [
+            (
+                "var x: number = \"one\";",
+                "Expected number for \"x\" but got string @(1:5)",
+            ),
+            (
+                "fun f(a: string): integer { return a; }",
+                "Expected integer for the return value but got string",
+            ),
+            (
+                "fun f(a: number) {} f(\"1\");",
+                "Expected number for argument 1 but got string",
+            ),
+            (
+                "fun f(a, b = 1) {} f();",
+                "Expected 1 to 2 arguments but got 0",
+            ),
+            (
+                "var x: integer = 1; x = 1.5;",
+                "Expected integer for \"x\" but got number",
+            ),
+            (
+                "var x: integer = 10 / 2;",
+                "Expected integer for \"x\" but got number",
+            ),
+            (
+                "const c = 1; var d: string = c;",
+                "Expected string for \"d\" but got integer",
+            ),
+            (
+                "var b = true - 1;",
+                "Operator \"-\" cannot be applied to boolean and integer",
+            ),
+            (
+                "var b = -\"x\";",
+                "Operator \"-\" cannot be applied to string",
+            ),
+            (
+                "var s: string? = nil; var t: string = s;",
+                "Expected string for \"t\"",
+            ),
+            ("var x = 1(2);", "Cannot call integer"),
+            ("for (var x in 1.5) {}", "Cannot iterate over number"),
+            ("var x: float = 1;", "Unknown type \"float\""),
+            (
+                "fun g() { var n: number = yield 1; var s: string = n; }",
+                "Expected string for \"s\" but got number",
+            ),
+            (
+                "fun g() { var n: float = yield 1; }",
+                "Unknown type \"float\"",
+            ),
+            (
+                "var jobs: channel = [];",
+                "Expected channel for \"jobs\" but got list",
+            ),
+            (
+                "fun f(a: number = \"s\") {}",
+                "Expected number for the default of \"a\"",
+            ),
+        ] {
+            let errors = check(input).unwrap_err();
+            assert!(errors[0].contains(expected), "{}: {:?}", input, errors);
+        }
+    }
+
+    #[test]
+    fn test_functions_are_known_before_their_declaration() {
+        let errors = check(
+            "fun main() { return half(\"ten\"); }
+            fun half(n: number): number { return n / 2; }
+            var bad: string = main();",
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("argument 1"), "{:?}", errors);
+    }
+}
@@ -25,6 +25,20 @@ pub enum ParseError {
         token_lexeme: String,
         coordinate: Coordinate,
     },
+    #[error("Cannot reassign const: {token_lexeme} {coordinate}")]
+    ConstReassignment {
+        token_lexeme: String,
+        coordinate: Coordinate,
+    },
+    #[error("Invalid yield: {msg} {coordinate}")]
+    InvalidYield {
+        msg: &'static str,
+        coordinate: Coordinate,
+    },
+    #[error("Unreachable match arm {coordinate}, an earlier arm matches everything it does")]
+    UnreachableArm { coordinate: Coordinate },
+    #[error("TypeError: {msg} {coordinate}")]
+    TypeMismatch { msg: String, coordinate: Coordinate },
     #[error("ParseError: Likely logical error with your parser...")]
     LikelyLogicalError,
 }
@@ -1,5 +1,8 @@
 pub mod ast;
+pub mod checker;
 pub mod errors;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
+pub mod symbol;
 pub mod token;
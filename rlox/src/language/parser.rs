@@ -1,6 +1,8 @@
-use crate::language::ast::{Expr, Stmt};
+use crate::language::ast::{Expr, MatchArm, Param, Pattern, Stmt, TypeAnnotation};
 use crate::language::errors::ParseError;
+use crate::language::resolver::Resolver;
 use crate::language::token::{Literal, Token, TokenType};
+use std::rc::Rc;
 
 const EQUALITIES: [TokenType; 2] = [TokenType::BangEqual, TokenType::EqualEqual];
 const COMPARISONS: [TokenType; 4] = [
@@ -10,8 +12,14 @@ const COMPARISONS: [TokenType; 4] = [
     TokenType::LessEqual,
 ];
 const ADDITIONS: [TokenType; 2] = [TokenType::Minus, TokenType::Plus];
-const MULTIPLICATIONS: [TokenType; 2] = [TokenType::Slash, TokenType::Star];
-const URNARIES: [TokenType; 2] = [TokenType::Bang, TokenType::Minus];
+const MULTIPLICATIONS: [TokenType; 4] = [
+    TokenType::Slash,
+    TokenType::Star,
+    TokenType::Percent,
+    TokenType::TildeSlash,
+];
+const SHIFTS: [TokenType; 2] = [TokenType::LessLess, TokenType::GreaterGreater];
+const URNARIES: [TokenType; 3] = [TokenType::Bang, TokenType::Minus, TokenType::Tilde];
 const LITERALS: [TokenType; 5] = [
     TokenType::Number,
     TokenType::String,
@@ -19,12 +27,20 @@ const LITERALS: [TokenType; 5] = [
     TokenType::False,
     TokenType::Nil,
 ];
-const ASSIGNMENTS: [TokenType; 5] = [
+const ASSIGNMENTS: [TokenType; 13] = [
     TokenType::Equal,
     TokenType::PlusEqual,
     TokenType::MinusEqual,
     TokenType::StarEqual,
     TokenType::SlashEqual,
+    TokenType::PercentEqual,
+    TokenType::StarStarEqual,
+    TokenType::TildeSlashEqual,
+    TokenType::AmpersandEqual,
+    TokenType::PipeEqual,
+    TokenType::CaretEqual,
+    TokenType::LessLessEqual,
+    TokenType::GreaterGreaterEqual,
 ];
 
 #[derive(Debug)]
@@ -42,6 +58,7 @@ impl TokenStream {
         self.tokens.get(self.current)
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<&Token> {
         if self.current >= self.tokens.len() {
             return None;
@@ -50,6 +67,10 @@ impl TokenStream {
         self.tokens.get(self.current - 1)
     }
 
+    pub fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.current + n)
+    }
+
     pub fn previous(&self) -> Option<&Token> {
         if self.current == 0 {
             return None;
@@ -70,6 +91,9 @@ impl TokenStream {
 pub struct Parser {
     stream: TokenStream,
     is_in_loop: bool,
+    // a match guard is followed by the arm's "=>", so "(x > 1) =>" must not be
+    // mistaken for an arrow function's parameter list.
+    is_in_guard: bool,
 }
 
 impl Parser {
@@ -77,6 +101,7 @@ impl Parser {
         Self {
             stream: TokenStream::new(tokens),
             is_in_loop: false,
+            is_in_guard: false,
         }
     }
 
@@ -93,18 +118,34 @@ impl Parser {
             }
         }
 
-        if errors.is_empty() {
-            Ok(stmts)
-        } else {
-            Err(errors)
+        if !errors.is_empty() {
+            return Err(errors);
         }
+
+        Resolver::new().resolve(&mut stmts)?;
+        Ok(stmts)
     }
 
     pub fn declaration(&mut self) -> Result<Stmt, ParseError> {
+        // doc comments arrive as trivia on the first token of a declaration (its
+        // keyword), they're moved onto the declared name where tooling expects them.
+        let doc = self.stream.peek().and_then(|toke| toke.doc.clone());
+        let mut stmt = self.undocumented_declaration()?;
+        if let Some(doc) = doc {
+            stmt.set_doc(doc);
+        }
+        Ok(stmt)
+    }
+
+    fn undocumented_declaration(&mut self) -> Result<Stmt, ParseError> {
         if self.match_exact(TokenType::Var).is_some() {
             self.var_declaration()
+        } else if self.match_exact(TokenType::Const).is_some() {
+            self.const_declaration()
         } else if self.match_exact(TokenType::Fun).is_some() {
             self.function_declaration()
+        } else if self.next_is(TokenType::Import) || self.next_is(TokenType::From) {
+            self.import_declaration()
         } else {
             self.statement()
         }
@@ -119,17 +160,57 @@ impl Parser {
             "function dec should be followed by \"(\"",
             TokenType::LeftParen,
         )?;
+        let params = self.parameters()?;
+        let return_type = self.type_annotation()?;
+
+        self.expect(
+            "function to be followed by a block scope",
+            TokenType::LeftBrace,
+        )?;
+
+        let body = self.block()?;
+
+        match body {
+            Stmt::Block { statements } => Ok(Stmt::Function {
+                name,
+                params,
+                return_type,
+                is_generator: statements.iter().any(Stmt::contains_yield),
+                body: statements.into(),
+                captures: Vec::new(),
+            }),
 
-        let mut params = Vec::with_capacity(255);
+            _ => Err(ParseError::LikelyLogicalError),
+        }
+    }
+
+    // the parameter list following "(", up to and including the closing ")".
+    fn parameters(&mut self) -> Result<Vec<Param>, ParseError> {
+        let mut params: Vec<Param> = Vec::new();
 
         if !self.next_is(TokenType::RightParen) {
             loop {
-                let param = self
-                    .expect("expected a list of parameters", TokenType::Identifier)?
-                    .clone();
+                let is_rest = self.match_exact(TokenType::Ellipsis).is_some();
+                let mut param = Param::new(
+                    self.expect("expected a list of parameters", TokenType::Identifier)?
+                        .clone(),
+                );
+                param.annotation = self.type_annotation()?;
+
+                if is_rest {
+                    param.is_rest = true;
+                } else if self.match_exact(TokenType::Equal).is_some() {
+                    param.default = Some(self.expression()?);
+                } else if params.iter().any(|p| p.default.is_some()) {
+                    return Err(ParseError::UnexpectedToken {
+                        msg: "parameters without a default can't follow ones with a default",
+                        token_lexeme: param.name.lexeme_or_empty(),
+                        coordinate: param.name.coordinate,
+                    });
+                }
                 params.push(param);
 
-                if !self.match_exact(TokenType::Comma).is_some() {
+                if is_rest || self.match_exact(TokenType::Comma).is_none() {
                     break;
                 }
             }
@@ -139,36 +220,134 @@ impl Parser {
             "function declaration to close parens properly",
             TokenType::RightParen,
         )?;
+        Ok(params)
+    }
 
-        self.expect(
-            "function to be followed by a block scope",
-            TokenType::LeftBrace,
-        )?;
-
-        let body = self.block()?;
+    // an optional ": type" or ": type?", see TypeAnnotation.
+    fn type_annotation(&mut self) -> Result<Option<TypeAnnotation>, ParseError> {
+        if self.match_exact(TokenType::Colon).is_none() {
+            return Ok(None);
+        }
+        // "nil" is a keyword but also the name of a type.
+        let name = self.take_token()?.clone();
+        if !matches!(name.token_type, TokenType::Identifier | TokenType::Nil) {
+            return Err(ParseError::UnexpectedToken {
+                msg: "expected a type name",
+                token_lexeme: name.lexeme_or_empty(),
+                coordinate: name.coordinate,
+            });
+        }
+        let nullable = self.match_exact(TokenType::Question).is_some();
+        Ok(Some(TypeAnnotation { name, nullable }))
+    }
 
-        match body {
-            Stmt::Block { statements } => Ok(Stmt::Function {
-                name,
-                params,
-                body: statements,
-            }),
+    pub fn import_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.take_token()?.clone();
+        let path = self
+            .expect("import requires a path string", TokenType::String)?
+            .clone();
 
-            _ => Err(ParseError::LikelyLogicalError),
+        let mut alias = None;
+        let mut names = Vec::new();
+
+        if keyword.token_type == TokenType::Import {
+            let as_keyword = self
+                .expect(
+                    "import path should be followed by \"as\"",
+                    TokenType::Identifier,
+                )?
+                .clone();
+            if as_keyword.lexeme_or_empty() != "as" {
+                return Err(ParseError::UnexpectedToken {
+                    msg: "import path should be followed by \"as\"",
+                    token_lexeme: as_keyword.lexeme_or_empty(),
+                    coordinate: as_keyword.coordinate,
+                });
+            }
+            alias = Some(
+                self.expect("import requires a module name", TokenType::Identifier)?
+                    .clone(),
+            );
+        } else {
+            self.expect(
+                "from path should be followed by \"import\"",
+                TokenType::Import,
+            )?;
+            loop {
+                names.push(
+                    self.expect("expected a list of names to import", TokenType::Identifier)?
+                        .clone(),
+                );
+                if self.match_exact(TokenType::Comma).is_none() {
+                    break;
+                }
+            }
         }
+
+        self.expect("unterminated import statement", TokenType::Semicolon)?;
+        Ok(Stmt::Import {
+            keyword,
+            path,
+            alias,
+            names,
+        })
     }
 
     pub fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        if self.next_is(TokenType::LeftBracket) || self.next_is(TokenType::LeftBrace) {
+            return self.destructuring_declaration();
+        }
+
         let name = self
             .expect("var statment missing identifier", TokenType::Identifier)?
             .clone();
+        let annotation = self.type_annotation()?;
         let initializer = if self.match_exact(TokenType::Equal).is_some() {
+            if self.next_is(TokenType::Yield) {
+                return self.yield_statement(Some(name), annotation);
+            }
             Some(self.expression()?)
         } else {
             None
         };
         self.expect("unterminated var statement", TokenType::Semicolon)?;
-        Ok(Stmt::Var { name, initializer })
+        Ok(Stmt::Var {
+            name,
+            annotation,
+            initializer,
+        })
+    }
+
+    fn destructuring_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self
+            .stream
+            .previous()
+            .cloned()
+            .ok_or(ParseError::LikelyLogicalError)?;
+        let pattern = self.single_pattern()?;
+        self.expect("destructuring must be initialized", TokenType::Equal)?;
+        let initializer = self.expression()?;
+        self.expect("unterminated var statement", TokenType::Semicolon)?;
+        Ok(Stmt::Destructure {
+            keyword,
+            pattern: Box::new(pattern),
+            initializer,
+        })
+    }
+
+    pub fn const_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self
+            .expect("const statment missing identifier", TokenType::Identifier)?
+            .clone();
+        let annotation = self.type_annotation()?;
+        self.expect("const must be initialized", TokenType::Equal)?;
+        let initializer = self.expression()?;
+        self.expect("unterminated const statement", TokenType::Semicolon)?;
+        Ok(Stmt::Const {
+            name,
+            annotation,
+            initializer,
+        })
     }
 
     pub fn statement(&mut self) -> Result<Stmt, ParseError> {
@@ -184,6 +363,12 @@ impl Parser {
             self.break_statement()
         } else if self.next_is(TokenType::Return) {
             self.return_statement()
+        } else if self.next_is(TokenType::Yield) {
+            self.yield_statement(None, None)
+        } else if self.next_is(TokenType::Throw) {
+            self.throw_statement()
+        } else if self.next_is(TokenType::Try) {
+            self.try_statement()
         } else {
             self.expression_statement()
         }
@@ -200,6 +385,75 @@ impl Parser {
         Ok(Stmt::Return { keyword, value })
     }
 
+    fn yield_statement(
+        &mut self,
+        target: Option<Token>,
+        annotation: Option<TypeAnnotation>,
+    ) -> Result<Stmt, ParseError> {
+        let keyword = self.take_token()?.clone();
+        let value = if self.next_is(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect("unterminated yield statement", TokenType::Semicolon)?;
+        Ok(Stmt::Yield {
+            keyword,
+            value,
+            target,
+            annotation,
+        })
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.take_token()?.clone();
+        let value = self.expression()?;
+        self.expect("unterminated throw statement", TokenType::Semicolon)?;
+        Ok(Stmt::Throw { keyword, value })
+    }
+
+    fn try_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.take_token()?.clone();
+        let body = self.block_statements("\"try\" should be followed by a block scope")?;
+
+        let (catch_name, catch_body) = if self.match_exact(TokenType::Catch).is_some() {
+            self.expect("\"catch\" requires \"(...\"", TokenType::LeftParen)?;
+            let name = self
+                .expect(
+                    "\"catch\" requires a name for the error",
+                    TokenType::Identifier,
+                )?
+                .clone();
+            self.expect("\"catch\" unclosed parens", TokenType::RightParen)?;
+            let body = self.block_statements("\"catch\" should be followed by a block scope")?;
+            (Some(name), Some(body))
+        } else {
+            (None, None)
+        };
+
+        let finally_body = if self.match_exact(TokenType::Finally).is_some() {
+            Some(self.block_statements("\"finally\" should be followed by a block scope")?)
+        } else {
+            None
+        };
+
+        if catch_body.is_none() && finally_body.is_none() {
+            return Err(ParseError::UnexpectedToken {
+                msg: "\"try\" requires a \"catch\" or \"finally\" clause",
+                token_lexeme: keyword.lexeme_or_empty(),
+                coordinate: keyword.coordinate,
+            });
+        }
+
+        Ok(Stmt::Try {
+            keyword,
+            body,
+            catch_name,
+            catch_body,
+            finally_body,
+        })
+    }
+
     fn break_statement(&mut self) -> Result<Stmt, ParseError> {
         let keyword = self.take_token()?.clone();
         if !self.is_in_loop {
@@ -229,8 +483,22 @@ impl Parser {
     }
 
     fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self
+            .stream
+            .previous()
+            .cloned()
+            .ok_or(ParseError::LikelyLogicalError)?;
         self.expect("for loop requires \"(...\'", TokenType::LeftParen)?;
 
+        let is_for_in = self.next_is(TokenType::Var)
+            && self
+                .stream
+                .peek_nth(2)
+                .is_some_and(|tok| tok.token_type == TokenType::In);
+        if is_for_in {
+            return self.for_in_statement(keyword);
+        }
+
         let intializer = if self.match_exact(TokenType::Semicolon).is_some() {
             None
         } else if self.match_exact(TokenType::Var).is_some() {
@@ -253,15 +521,35 @@ impl Parser {
         let increment = if self.match_exact(TokenType::RightParen).is_some() {
             None
         } else {
-            let inc = Some(Stmt::Expression {
-                expression: self.expression()?,
-            });
+            let inc = Some(self.expression()?);
             self.expect("for loop unclosed parens", TokenType::RightParen)?;
             inc
         };
 
         let body = self.statement()?;
-        Ok(desugar_for_loop(intializer, condition, increment, body))
+        Ok(Stmt::For {
+            initializer: intializer.map(Box::new),
+            condition: condition.unwrap_or(literal_true()),
+            increment,
+            body: Box::new(body),
+        })
+    }
+
+    fn for_in_statement(&mut self, keyword: Token) -> Result<Stmt, ParseError> {
+        self.expect("for-in loop requires \"var\"", TokenType::Var)?;
+        let name = self
+            .expect("for-in loop missing identifier", TokenType::Identifier)?
+            .clone();
+        self.expect("for-in loop missing \"in\"", TokenType::In)?;
+        let iterable = self.expression()?;
+        self.expect("for loop unclosed parens", TokenType::RightParen)?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::ForIn {
+            keyword,
+            name,
+            iterable,
+            body,
+        })
     }
 
     fn while_statement(&mut self) -> Result<Stmt, ParseError> {
@@ -300,6 +588,14 @@ impl Parser {
         Ok(Stmt::Block { statements })
     }
 
+    fn block_statements(&mut self, msg: &'static str) -> Result<Vec<Stmt>, ParseError> {
+        self.expect(msg, TokenType::LeftBrace)?;
+        match self.block()? {
+            Stmt::Block { statements } => Ok(statements),
+            _ => Err(ParseError::LikelyLogicalError),
+        }
+    }
+
     fn print_statement(&mut self) -> Result<Stmt, ParseError> {
         let expression = self.expression()?;
         self.expect("unterminated \"print\" statement", TokenType::Semicolon)?;
@@ -317,30 +613,98 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr, ParseError> {
-        let expr = self.logical_or()?;
+        let expr = self.ternary()?;
 
         if let Some(tok) = self.match_one_of(&ASSIGNMENTS) {
-            if let Expr::Variable { name } = expr {
-                let tok = tok.clone();
-                let value = self.assignment()?;
-                if tok.token_type != TokenType::Equal {
-                    return desugar_assignment(name, tok.token_type, value);
+            let tok = tok.clone();
+            match expr {
+                Expr::Variable { name } => {
+                    let value = self.assignment()?;
+                    if tok.token_type != TokenType::Equal {
+                        return desugar_assignment(name, tok.token_type, value);
+                    }
+                    return Ok(Expr::Assign {
+                        name,
+                        value: Box::new(value),
+                    });
+                }
+                // compound assignment would evaluate the object and index twice.
+                Expr::Get { object, name } if tok.token_type == TokenType::Equal => {
+                    let value = self.assignment()?;
+                    return Ok(Expr::Set {
+                        object,
+                        name,
+                        value: Box::new(value),
+                    });
+                }
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } if tok.token_type == TokenType::Equal => {
+                    let value = self.assignment()?;
+                    return Ok(Expr::SetIndex {
+                        object,
+                        bracket,
+                        index,
+                        value: Box::new(value),
+                    });
+                }
+                Expr::List { .. } | Expr::Map { .. } if tok.token_type == TokenType::Equal => {
+                    let pattern = assignment_pattern(expr, &tok)?;
+                    let value = self.assignment()?;
+                    return Ok(Expr::DestructureAssign {
+                        pattern: Box::new(pattern),
+                        equals: tok,
+                        value: Box::new(value),
+                    });
+                }
+                _ => {
+                    return Err(ParseError::InvalidAssignmentTarget {
+                        token_lexeme: tok.lexeme_or_empty(),
+                        coordinate: tok.coordinate,
+                    });
                 }
-                return Ok(Expr::Assign {
-                    name,
-                    value: Box::new(value),
-                });
-            } else {
-                return Err(ParseError::InvalidAssignmentTarget {
-                    token_lexeme: tok.lexeme_or_empty(),
-                    coordinate: tok.coordinate.clone(),
-                });
             }
         }
 
         Ok(expr)
     }
 
+    // right associative, so "a ? b : c ? d : e" is "a ? b : (c ? d : e)".
+    fn ternary(&mut self) -> Result<Expr, ParseError> {
+        let condition = self.coalesce()?;
+
+        if self.match_exact(TokenType::Question).is_none() {
+            return Ok(condition);
+        }
+
+        let then_branch = self.assignment()?;
+        self.expect("expected \":\" in conditional expression", TokenType::Colon)?;
+        let else_branch = self.ternary()?;
+        Ok(Expr::Ternary {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+        })
+    }
+
+    fn coalesce(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.logical_or()?;
+
+        while let Some(tok) = self.match_exact(TokenType::QuestionQuestion) {
+            let operator = tok.clone();
+            let right = self.logical_or()?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
     fn logical_or(&mut self) -> Result<Expr, ParseError> {
         let mut expr = self.logical_and()?;
 
@@ -390,11 +754,11 @@ impl Parser {
     }
 
     fn comparison(&mut self) -> Result<Expr, ParseError> {
-        let mut term = self.term()?;
+        let mut term = self.bit_or()?;
 
         while let Some(tok) = self.match_one_of(&COMPARISONS) {
             let operator = tok.clone();
-            let right = self.term()?;
+            let right = self.bit_or()?;
             term = Expr::Binary {
                 left: Box::new(term),
                 operator,
@@ -405,6 +769,44 @@ impl Parser {
         Ok(term)
     }
 
+    // like python (and unlike c) the bitwise operators bind tighter than
+    // comparisons, so "a & 1 == 0" is "(a & 1) == 0".
+    fn bit_or(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[TokenType::Pipe], Self::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[TokenType::Caret], Self::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&[TokenType::Ampersand], Self::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expr, ParseError> {
+        self.left_associative(&SHIFTS, Self::term)
+    }
+
+    fn left_associative(
+        &mut self,
+        operators: &[TokenType],
+        operand: fn(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        let mut expr = operand(self)?;
+
+        while let Some(tok) = self.match_one_of(operators) {
+            let operator = tok.clone();
+            let right = operand(self)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
     fn term(&mut self) -> Result<Expr, ParseError> {
         let mut factor = self.factor()?;
 
@@ -447,7 +849,25 @@ impl Parser {
             });
         }
 
-        self.call()
+        self.power()
+    }
+
+    // right associative and tighter than unary on its left, "-2 ** 2" is -4
+    // while "2 ** -1" is 0.5.
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.call()?;
+
+        if let Some(tok) = self.match_exact(TokenType::StarStar) {
+            let operator = tok.clone();
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary {
+                left: Box::new(base),
+                operator,
+                right: Box::new(exponent),
+            });
+        }
+
+        Ok(base)
     }
 
     fn call(&mut self) -> Result<Expr, ParseError> {
@@ -455,6 +875,37 @@ impl Parser {
         loop {
             if self.match_exact(TokenType::LeftParen).is_some() {
                 expr = self.finish_call(expr)?;
+            } else if self.match_exact(TokenType::Dot).is_some() {
+                let name = self
+                    .expect(
+                        "expected a property name after \".\"",
+                        TokenType::Identifier,
+                    )?
+                    .clone();
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if self.match_exact(TokenType::QuestionDot).is_some() {
+                let name = self
+                    .expect(
+                        "expected a property name after \"?.\"",
+                        TokenType::Identifier,
+                    )?
+                    .clone();
+                expr = Expr::SafeGet {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if let Some(tok) = self.match_exact(TokenType::LeftBracket) {
+                let bracket = tok.clone();
+                let index = self.outside_of_guard(Self::expression)?;
+                self.expect("unterminated index expression", TokenType::RightBracket)?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
             } else {
                 break;
             }
@@ -463,12 +914,16 @@ impl Parser {
     }
 
     fn finish_call(&mut self, expr: Expr) -> Result<Expr, ParseError> {
+        self.outside_of_guard(|parser| parser.call_arguments(expr))
+    }
+
+    fn call_arguments(&mut self, expr: Expr) -> Result<Expr, ParseError> {
         let mut args = Vec::with_capacity(255); // max number of arguments.
 
         if !self.next_is(TokenType::RightParen) {
             loop {
                 args.push(self.expression()?);
-                if !self.match_exact(TokenType::Comma).is_some() {
+                if self.match_exact(TokenType::Comma).is_none() {
                     break;
                 };
             }
@@ -499,15 +954,30 @@ impl Parser {
         }
 
         if tok.token_type == TokenType::LeftParen {
-            let expr = self.expression()?;
+            if !self.is_in_guard && self.is_arrow_function() {
+                return self.arrow_function();
+            }
+            let expr = self.outside_of_guard(Self::expression)?;
             self.expect("unterminated left parens", TokenType::RightParen)?;
             return Ok(Expr::Grouping {
                 expression: Box::new(expr),
             });
         }
 
+        if tok.token_type == TokenType::LeftBracket {
+            return self.outside_of_guard(|parser| parser.list_expression(tok));
+        }
+
+        if tok.token_type == TokenType::LeftBrace {
+            return self.outside_of_guard(|parser| parser.map_expression(tok));
+        }
+
         if tok.token_type == TokenType::Fun {
-            return self.function_expression();
+            return self.outside_of_guard(Self::function_expression);
+        }
+
+        if tok.token_type == TokenType::Match {
+            return self.outside_of_guard(|parser| parser.match_expression(tok));
         }
 
         Err(ParseError::UnexpectedToken {
@@ -517,31 +987,233 @@ impl Parser {
         })
     }
 
-    fn function_expression(&mut self) -> Result<Expr, ParseError> {
+    fn list_expression(&mut self, bracket: Token) -> Result<Expr, ParseError> {
+        let mut elements = Vec::new();
+
+        if !self.next_is(TokenType::RightBracket) {
+            loop {
+                elements.push(self.expression()?);
+                if self.match_exact(TokenType::Comma).is_none() {
+                    break;
+                }
+            }
+        }
+
+        self.expect("unterminated list literal", TokenType::RightBracket)?;
+        Ok(Expr::List { bracket, elements })
+    }
+
+    fn map_expression(&mut self, brace: Token) -> Result<Expr, ParseError> {
+        let mut entries = Vec::new();
+
+        if !self.next_is(TokenType::RightBrace) {
+            loop {
+                let key = self.take_token()?.clone();
+                if !matches!(key.token_type, TokenType::String | TokenType::Identifier) {
+                    return Err(ParseError::UnexpectedToken {
+                        msg: "map keys should be strings or identifiers",
+                        token_lexeme: key.lexeme_or_empty(),
+                        coordinate: key.coordinate,
+                    });
+                }
+                self.expect("map key should be followed by \":\"", TokenType::Colon)?;
+                entries.push((key, self.expression()?));
+                if self.match_exact(TokenType::Comma).is_none() {
+                    break;
+                }
+            }
+        }
+
+        self.expect("unterminated map literal", TokenType::RightBrace)?;
+        Ok(Expr::Map { brace, entries })
+    }
+
+    fn match_expression(&mut self, keyword: Token) -> Result<Expr, ParseError> {
         self.expect(
-            "function expression should be followed by \"(\"",
+            "\"match\" should be followed by \"(\"",
             TokenType::LeftParen,
         )?;
+        let subject = self.expression()?;
+        self.expect("unterminated match subject", TokenType::RightParen)?;
+        self.expect(
+            "match subject should be followed by a block of arms",
+            TokenType::LeftBrace,
+        )?;
 
-        let mut params = Vec::with_capacity(255);
+        let mut arms = Vec::new();
+        while !self.next_is(TokenType::RightBrace) {
+            let pattern = self.pattern()?;
+            let guard = match self.match_exact(TokenType::If) {
+                Some(_) => Some(self.guard()?),
+                None => None,
+            };
+            self.expect(
+                "match pattern should be followed by \"=>\"",
+                TokenType::Arrow,
+            )?;
+            let body = self.expression()?;
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                body,
+            });
+            if self.match_exact(TokenType::Comma).is_none() {
+                break;
+            }
+        }
 
-        if !self.next_is(TokenType::RightParen) {
-            loop {
-                let param = self
-                    .expect("expected a list of parameters", TokenType::Identifier)?
+        self.expect("unterminated match arms", TokenType::RightBrace)?;
+        Ok(Expr::Match {
+            keyword,
+            subject: Box::new(subject),
+            arms,
+        })
+    }
+
+    // only the guard's own top level can be mistaken for an arrow function,
+    // "x if (a) => a" ends the guard at the arm's "=>".
+    fn guard(&mut self) -> Result<Expr, ParseError> {
+        let was_in_guard = std::mem::replace(&mut self.is_in_guard, true);
+        let guard = self.expression();
+        self.is_in_guard = was_in_guard;
+        guard
+    }
+
+    // anything nested in brackets is closed off before the arm's "=>", so
+    // arrow functions are fine in there again.
+    fn outside_of_guard<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let was_in_guard = std::mem::replace(&mut self.is_in_guard, false);
+        let result = parse(self);
+        self.is_in_guard = was_in_guard;
+        result
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        let first = self.single_pattern()?;
+        if !self.next_is(TokenType::Pipe) {
+            return Ok(first);
+        }
+
+        let mut alternatives = vec![first];
+        while self.match_exact(TokenType::Pipe).is_some() {
+            alternatives.push(self.single_pattern()?);
+        }
+
+        let alternatives = Pattern::Alternatives(alternatives);
+        if let Some(name) = alternatives.bindings().first() {
+            return Err(ParseError::UnexpectedToken {
+                msg: "alternative patterns can't bind names",
+                token_lexeme: name.lexeme_or_empty(),
+                coordinate: name.coordinate.clone(),
+            });
+        }
+        Ok(alternatives)
+    }
+
+    fn single_pattern(&mut self) -> Result<Pattern, ParseError> {
+        let tok = self.take_token()?.clone();
+
+        match tok.token_type {
+            TokenType::Identifier if tok.lexeme_or_empty() == "_" => Ok(Pattern::Wildcard(tok)),
+            TokenType::Identifier => Ok(Pattern::Binding(tok)),
+            t if LITERALS.contains(&t) => Ok(Pattern::Literal(tok)),
+            // patterns aren't expressions, so negative numbers are folded here.
+            TokenType::Minus => {
+                let mut number = self
+                    .expect(
+                        "\"-\" in a pattern should be followed by a number",
+                        TokenType::Number,
+                    )?
                     .clone();
-                params.push(param);
+                number.literal = match number.literal {
+                    Literal::Integer(i) => Literal::Integer(-i),
+                    Literal::Number(n) => Literal::Number(-n),
+                    other => other,
+                };
+                number.lexeme = Some(format!("-{}", number.lexeme_or_empty()));
+                number.coordinate = tok.coordinate;
+                Ok(Pattern::Literal(number))
+            }
+            TokenType::LeftBracket => self.list_pattern(tok),
+            TokenType::LeftBrace => self.map_pattern(tok),
+            _ => Err(ParseError::UnexpectedToken {
+                msg: "parsing a pattern",
+                token_lexeme: tok.lexeme_or_empty(),
+                coordinate: tok.coordinate,
+            }),
+        }
+    }
+
+    fn list_pattern(&mut self, bracket: Token) -> Result<Pattern, ParseError> {
+        let mut elements = Vec::new();
+        let mut rest = None;
+
+        if !self.next_is(TokenType::RightBracket) {
+            loop {
+                if self.match_exact(TokenType::Ellipsis).is_some() {
+                    let name = self.expect(
+                        "\"...\" should be followed by a name",
+                        TokenType::Identifier,
+                    )?;
+                    rest = Some(name.clone());
+                    break;
+                }
+                elements.push(self.pattern()?);
+                if self.match_exact(TokenType::Comma).is_none() {
+                    break;
+                }
+            }
+        }
 
-                if !self.match_exact(TokenType::Comma).is_some() {
+        self.expect("unterminated list pattern", TokenType::RightBracket)?;
+        Ok(Pattern::List {
+            bracket,
+            elements,
+            rest,
+        })
+    }
+
+    fn map_pattern(&mut self, brace: Token) -> Result<Pattern, ParseError> {
+        let mut entries = Vec::new();
+
+        if !self.next_is(TokenType::RightBrace) {
+            loop {
+                let key = self.take_token()?.clone();
+                let pattern =
+                    match key.token_type {
+                        TokenType::String | TokenType::Identifier
+                            if self.match_exact(TokenType::Colon).is_some() =>
+                        {
+                            self.pattern()?
+                        }
+                        TokenType::Identifier => Pattern::Binding(key.clone()),
+                        _ => return Err(ParseError::UnexpectedToken {
+                            msg: "map pattern keys should be a name or a string followed by \":\"",
+                            token_lexeme: key.lexeme_or_empty(),
+                            coordinate: key.coordinate,
+                        }),
+                    };
+                entries.push((key, pattern));
+                if self.match_exact(TokenType::Comma).is_none() {
                     break;
                 }
             }
         }
 
+        self.expect("unterminated map pattern", TokenType::RightBrace)?;
+        Ok(Pattern::Map { brace, entries })
+    }
+
+    fn function_expression(&mut self) -> Result<Expr, ParseError> {
         self.expect(
-            "function declaration to close parens properly",
-            TokenType::RightParen,
+            "function expression should be followed by \"(\"",
+            TokenType::LeftParen,
         )?;
+        let params = self.parameters()?;
+        let return_type = self.type_annotation()?;
 
         self.expect(
             "function to be followed by a block scope",
@@ -553,13 +1225,73 @@ impl Parser {
         match body {
             Stmt::Block { statements } => Ok(Expr::Function {
                 params,
-                body: statements,
+                return_type,
+                is_generator: statements.iter().any(Stmt::contains_yield),
+                body: statements.into(),
+                captures: Vec::new(),
             }),
 
             _ => Err(ParseError::LikelyLogicalError),
         }
     }
 
+    // having just taken a "(", decides between a grouping and the parameter list
+    // of an arrow function by looking past the matching ")" for a "=>".
+    fn is_arrow_function(&self) -> bool {
+        let mut depth = 0;
+        let mut n = 0;
+        while let Some(tok) = self.stream.peek_nth(n) {
+            match tok.token_type {
+                TokenType::LeftParen => depth += 1,
+                TokenType::RightParen if depth == 0 => {
+                    return self
+                        .stream
+                        .peek_nth(n + 1)
+                        .is_some_and(|t| t.token_type == TokenType::Arrow);
+                }
+                TokenType::RightParen => depth -= 1,
+                TokenType::Semicolon | TokenType::Eof => return false,
+                _ => {}
+            }
+            n += 1;
+        }
+        false
+    }
+
+    // "(a, b) => a + b" is sugar for "fun (a, b) { return a + b; }" while
+    // "(a) => { ... }" takes a block body as is.
+    fn arrow_function(&mut self) -> Result<Expr, ParseError> {
+        let params = self.parameters()?;
+        let arrow = self
+            .expect("parameter list to be followed by \"=>\"", TokenType::Arrow)?
+            .clone();
+
+        if self.match_exact(TokenType::LeftBrace).is_some() {
+            return match self.block()? {
+                Stmt::Block { statements } => Ok(Expr::Function {
+                    params,
+                    return_type: None,
+                    is_generator: statements.iter().any(Stmt::contains_yield),
+                    body: statements.into(),
+                    captures: Vec::new(),
+                }),
+                _ => Err(ParseError::LikelyLogicalError),
+            };
+        }
+
+        let value = self.expression()?;
+        Ok(Expr::Function {
+            params,
+            return_type: None,
+            body: Rc::new([Stmt::Return {
+                keyword: arrow,
+                value: Some(value),
+            }]),
+            captures: Vec::new(),
+            is_generator: false,
+        })
+    }
+
     fn syncronize(&mut self) {
         while let Some(tok) = self.stream.next() {
             if tok.token_type == TokenType::Semicolon {
@@ -573,7 +1305,13 @@ impl Parser {
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => return,
+                | TokenType::Return
+                | TokenType::Yield
+                | TokenType::Throw
+                | TokenType::Try
+                | TokenType::Const
+                | TokenType::Import
+                | TokenType::From => return,
                 _ => {}
             }
         }
@@ -599,7 +1337,7 @@ impl Parser {
     }
 
     fn next_is(&mut self, t: TokenType) -> bool {
-        self.stream.peek().map_or(false, |tok| tok.token_type == t)
+        self.stream.peek().is_some_and(|tok| tok.token_type == t)
     }
 
     fn match_exact(&mut self, t: TokenType) -> Option<&Token> {
@@ -629,12 +1367,48 @@ fn literal_true() -> Expr {
     }
 }
 
+// reinterprets a list or map literal on the left of "=" as a pattern of
+// variables to assign to, e.g. "[a, b] = [b, a]" or "{x: a, y: _} = point".
+fn assignment_pattern(expr: Expr, equals: &Token) -> Result<Pattern, ParseError> {
+    match expr {
+        Expr::Variable { name } if name.lexeme_or_empty() == "_" => Ok(Pattern::Wildcard(name)),
+        Expr::Variable { name } => Ok(Pattern::Binding(name)),
+        Expr::List { bracket, elements } => Ok(Pattern::List {
+            bracket,
+            elements: elements
+                .into_iter()
+                .map(|e| assignment_pattern(e, equals))
+                .collect::<Result<_, _>>()?,
+            rest: None,
+        }),
+        Expr::Map { brace, entries } => Ok(Pattern::Map {
+            brace,
+            entries: entries
+                .into_iter()
+                .map(|(key, e)| Ok((key, assignment_pattern(e, equals)?)))
+                .collect::<Result<_, ParseError>>()?,
+        }),
+        _ => Err(ParseError::InvalidAssignmentTarget {
+            token_lexeme: equals.lexeme_or_empty(),
+            coordinate: equals.coordinate.clone(),
+        }),
+    }
+}
+
 fn desugar_assignment(name: Token, t: TokenType, value: Expr) -> Result<Expr, ParseError> {
     let t = match t {
         TokenType::PlusEqual => Ok(TokenType::Plus),
         TokenType::MinusEqual => Ok(TokenType::Minus),
         TokenType::StarEqual => Ok(TokenType::Star),
         TokenType::SlashEqual => Ok(TokenType::Slash),
+        TokenType::PercentEqual => Ok(TokenType::Percent),
+        TokenType::StarStarEqual => Ok(TokenType::StarStar),
+        TokenType::TildeSlashEqual => Ok(TokenType::TildeSlash),
+        TokenType::AmpersandEqual => Ok(TokenType::Ampersand),
+        TokenType::PipeEqual => Ok(TokenType::Pipe),
+        TokenType::CaretEqual => Ok(TokenType::Caret),
+        TokenType::LessLessEqual => Ok(TokenType::LessLess),
+        TokenType::GreaterGreaterEqual => Ok(TokenType::GreaterGreater),
         _ => Err(ParseError::InvalidAssignmentTarget {
             token_lexeme: name.lexeme_or_empty(),
             coordinate: name.coordinate.clone(),
@@ -651,47 +1425,6 @@ fn desugar_assignment(name: Token, t: TokenType, value: Expr) -> Result<Expr, Pa
     })
 }
 
-fn while_loop_with_increment(condition: Expr, body: Stmt, increment: Option<Stmt>) -> Stmt {
-    match body {
-        Stmt::Block { mut statements } => {
-            increment.map(|i| statements.push(i));
-            Stmt::While {
-                condition,
-                body: Box::new(Stmt::Block { statements }),
-            }
-        }
-
-        other => {
-            let mut statements = vec![other];
-            increment.map(|i| statements.push(i));
-            Stmt::While {
-                condition,
-                body: Box::new(Stmt::Block { statements }),
-            }
-        }
-    }
-}
-
-fn desugar_for_loop(
-    init: Option<Stmt>,
-    condition: Option<Expr>,
-    increment: Option<Stmt>,
-    body: Stmt,
-) -> Stmt {
-    let mut statements = match init {
-        Some(s) => vec![s],
-        _ => vec![],
-    };
-
-    statements.push(while_loop_with_increment(
-        condition.unwrap_or(literal_true()),
-        body,
-        increment,
-    ));
-
-    Stmt::Block { statements }
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -727,11 +1460,11 @@ mod test {
         }
     }
 
-    fn number(v: f64, i: usize, r: usize, c: usize) -> Token {
+    fn integer(v: i64, i: usize, r: usize, c: usize) -> Token {
         Token::new(
             TokenType::Number,
             Some(v.to_string()),
-            Literal::Number(v),
+            Literal::Integer(v),
             coordinate(i, r, c),
         )
     }
@@ -772,16 +1505,16 @@ mod test {
     #[test]
     fn test_basic_integration() {
         let input = "1 + 2 * 3;";
-        let tokens = Scanner::new(&input).scan_tokens().unwrap();
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
         let tree = Parser::new(tokens).parse().unwrap();
 
         let expected = vec![expression_stmt(binary(
-            literal(number(1.0, 0, 1, 1)),
+            literal(integer(1, 0, 1, 1)),
             operator(TokenType::Plus, 2, 1, 3),
             binary(
-                literal(number(2.0, 4, 1, 5)),
+                literal(integer(2, 4, 1, 5)),
                 operator(TokenType::Star, 6, 1, 7),
-                literal(number(3.0, 8, 1, 9)),
+                literal(integer(3, 8, 1, 9)),
             ),
         ))];
 
@@ -791,12 +1524,12 @@ mod test {
     #[test]
     fn test_unary_operator() {
         let input = "-1;";
-        let tokens = Scanner::new(&input).scan_tokens().unwrap();
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
         let tree = Parser::new(tokens).parse().unwrap();
 
         let expected = vec![expression_stmt(unary(
             operator(TokenType::Minus, 0, 1, 1),
-            literal(number(1.0, 1, 1, 2)),
+            literal(integer(1, 1, 1, 2)),
         ))];
 
         assert_eq!(tree, expected);
@@ -805,17 +1538,17 @@ mod test {
     #[test]
     fn test_grouping() {
         let input = "(1 + 2) * 3;";
-        let tokens = Scanner::new(&input).scan_tokens().unwrap();
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
         let tree = Parser::new(tokens).parse().unwrap();
 
         let expected = vec![expression_stmt(binary(
             grouping(binary(
-                literal(number(1.0, 1, 1, 2)),
+                literal(integer(1, 1, 1, 2)),
                 operator(TokenType::Plus, 3, 1, 4),
-                literal(number(2.0, 5, 1, 6)),
+                literal(integer(2, 5, 1, 6)),
             )),
             operator(TokenType::Star, 8, 1, 9),
-            literal(number(3.0, 10, 1, 11)),
+            literal(integer(3, 10, 1, 11)),
         ))];
 
         assert_eq!(tree, expected);
@@ -824,7 +1557,7 @@ mod test {
     #[test]
     fn test_invalid_expression() {
         let input = "1 +;";
-        let tokens = Scanner::new(&input).scan_tokens().unwrap();
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
         let tree = Parser::new(tokens).parse();
         assert!(tree.is_err());
 
@@ -836,7 +1569,7 @@ mod test {
     #[test]
     fn test_concate_strings() {
         let input = "\"hello\" + \"world\";";
-        let tokens = Scanner::new(&input).scan_tokens().unwrap();
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
         let tree = Parser::new(tokens).parse().unwrap();
 
         let expected = vec![expression_stmt(binary(
@@ -855,11 +1588,479 @@ mod test {
         let tree = Parser::new(tokens).parse().unwrap();
 
         let expected = vec![expression_stmt(binary(
-            literal(number(1.0, 0, 1, 1)),
+            literal(integer(1, 0, 1, 1)),
             operator(TokenType::Plus, 2, 1, 3),
-            literal(number(2.0, 5, 2, 1)),
+            literal(integer(2, 5, 2, 1)),
         ))];
 
         assert_eq!(tree, expected)
     }
+
+    #[test]
+    fn test_list_literal_and_index() {
+        let input = "[1, 2][0];";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        let bracket = |i: usize, c: usize| {
+            Token::new(
+                TokenType::LeftBracket,
+                Some("[".to_string()),
+                Literal::Nil,
+                coordinate(i, 1, c),
+            )
+        };
+
+        let expected = vec![expression_stmt(Expr::Index {
+            object: Box::new(Expr::List {
+                bracket: bracket(0, 1),
+                elements: vec![literal(integer(1, 1, 1, 2)), literal(integer(2, 4, 1, 5))],
+            }),
+            bracket: bracket(6, 7),
+            index: Box::new(literal(integer(0, 7, 1, 8))),
+        })];
+
+        assert_eq!(tree, expected);
+    }
+
+    #[test]
+    fn test_map_literal_and_index_assignment() {
+        let input = "m = {a: 1, \"b\": 2}; m[\"a\"] = 3;";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        match &tree[0] {
+            Stmt::Expression {
+                expression: Expr::Assign { value, .. },
+            } => match value.as_ref() {
+                Expr::Map { entries, .. } => {
+                    let keys: Vec<String> =
+                        entries.iter().map(|(k, _)| k.lexeme_or_empty()).collect();
+                    assert_eq!(keys, vec!["a".to_string(), "\"b\"".to_string()]);
+                }
+                other => panic!("expected a map literal, got {:?}", other),
+            },
+            other => panic!("expected an assignment, got {:?}", other),
+        }
+
+        assert!(matches!(
+            &tree[1],
+            Stmt::Expression {
+                expression: Expr::SetIndex { .. }
+            }
+        ));
+    }
+
+    #[test]
+    fn test_compound_index_assignment_is_rejected() {
+        let input = "m[0] += 1;";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert!(matches!(
+            errors[0],
+            ParseError::InvalidAssignmentTarget { .. }
+        ));
+    }
+
+    #[test]
+    fn test_try_catch_finally() {
+        let input = "try { throw 1; } catch (e) { print e; } finally { print 2; }";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        match &tree[0] {
+            Stmt::Try {
+                body,
+                catch_name,
+                catch_body,
+                finally_body,
+                ..
+            } => {
+                assert!(matches!(body[0], Stmt::Throw { .. }));
+                assert_eq!(catch_name.as_ref().unwrap().lexeme_or_empty(), "e");
+                assert_eq!(catch_body.as_ref().unwrap().len(), 1);
+                assert_eq!(finally_body.as_ref().unwrap().len(), 1);
+            }
+            other => panic!("expected a try statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_try_requires_catch_or_finally() {
+        let input = "try { print 1; }";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert!(matches!(errors[0], ParseError::UnexpectedToken { .. }));
+    }
+
+    #[test]
+    fn test_parameter_forms() {
+        let tokens = Scanner::new("fun f(a, b = 1, ...rest) {}")
+            .scan_tokens()
+            .unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        match &tree[0] {
+            Stmt::Function { params, .. } => {
+                assert_eq!(params.len(), 3);
+                assert!(params[0].default.is_none() && !params[0].is_rest);
+                assert!(matches!(params[1].default, Some(Expr::Literal { .. })));
+                assert!(params[2].is_rest);
+                assert_eq!(params[2].name.lexeme_or_empty(), "rest");
+            }
+            other => panic!("expected a function, got {:?}", other),
+        }
+
+        for input in [
+            "fun f(a = 1, b) {}",
+            "fun f(...rest, a) {}",
+            "fun f(...rest = 1) {}",
+            "fun f(..rest) {}",
+        ] {
+            let parsed = Scanner::new(input)
+                .scan_tokens()
+                .map(|tokens| Parser::new(tokens).parse());
+            assert!(!matches!(parsed, Ok(Ok(_))), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let tokens =
+            Scanner::new("fun f(a: number, b: string? = nil): list {} var x: map; var y = 1;")
+                .scan_tokens()
+                .unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        let type_name = |annotation: &Option<TypeAnnotation>| {
+            annotation
+                .as_ref()
+                .map(|a| (a.name.lexeme_or_empty(), a.nullable))
+        };
+        match &tree[0] {
+            Stmt::Function {
+                params,
+                return_type,
+                ..
+            } => {
+                assert_eq!(
+                    type_name(&params[0].annotation),
+                    Some(("number".into(), false))
+                );
+                assert_eq!(
+                    type_name(&params[1].annotation),
+                    Some(("string".into(), true))
+                );
+                assert!(params[1].default.is_some());
+                assert_eq!(type_name(return_type), Some(("list".into(), false)));
+            }
+            other => panic!("expected a function, got {:?}", other),
+        }
+        assert!(matches!(
+            &tree[1],
+            Stmt::Var {
+                annotation: Some(_),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &tree[2],
+            Stmt::Var {
+                annotation: None,
+                ..
+            }
+        ));
+
+        for input in ["var x: = 1;", "fun f(a: 1) {}", "const c: string;"] {
+            let tokens = Scanner::new(input).scan_tokens().unwrap();
+            assert!(Parser::new(tokens).parse().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_arrow_functions() {
+        let parse = |input: &str| {
+            let tokens = Scanner::new(input).scan_tokens().unwrap();
+            Parser::new(tokens).parse()
+        };
+
+        let tree = parse("var f = (x, y = 1) => x * y;").unwrap();
+        match &tree[0] {
+            Stmt::Var {
+                initializer: Some(Expr::Function { params, body, .. }),
+                ..
+            } => {
+                assert_eq!(params.len(), 2);
+                assert!(matches!(
+                    &body[..],
+                    [Stmt::Return {
+                        value: Some(Expr::Binary { .. }),
+                        ..
+                    }]
+                ));
+            }
+            other => panic!("expected an arrow function, got {:?}", other),
+        }
+
+        let tree = parse("var f = () => { print 1; print 2; };").unwrap();
+        assert!(matches!(
+            &tree[0],
+            Stmt::Var { initializer: Some(Expr::Function { params, body, .. }), .. }
+                if params.is_empty() && body.len() == 2
+        ));
+
+        // a parenthesized expression is still a grouping.
+        let tree = parse("var g = ((1) + (2)) * 3;").unwrap();
+        assert!(matches!(
+            &tree[0],
+            Stmt::Var {
+                initializer: Some(Expr::Binary { .. }),
+                ..
+            }
+        ));
+
+        assert!(parse("var f = (1 + 2) => 3;").is_err());
+    }
+
+    #[test]
+    fn test_match_patterns() {
+        let parse = |input: &str| {
+            let tokens = Scanner::new(input).scan_tokens().unwrap();
+            Parser::new(tokens).parse()
+        };
+
+        let tree = parse(
+            "var r = match (v) { -1 | 0 => 1, [x, ...rest] if x > 0 => 2, {a, \"b\": _} => 3, _ => 4 };",
+        )
+        .unwrap();
+        let arms = match &tree[0] {
+            Stmt::Var {
+                initializer: Some(Expr::Match { arms, .. }),
+                ..
+            } => arms,
+            other => panic!("expected a match expression, got {:?}", other),
+        };
+
+        assert_eq!(arms.len(), 4);
+        match &arms[0].pattern {
+            Pattern::Alternatives(alternatives) => {
+                assert!(matches!(
+                    &alternatives[0],
+                    Pattern::Literal(tok) if tok.literal == Literal::Integer(-1)
+                ));
+            }
+            other => panic!("expected alternatives, got {:?}", other),
+        }
+        assert!(matches!(
+            &arms[1].pattern,
+            Pattern::List { elements, rest: Some(_), .. } if elements.len() == 1
+        ));
+        assert!(arms[1].guard.is_some());
+
+        let tree = parse("var r = match (v) { x if (x > 1) => \"big\", _ => \"small\" };").unwrap();
+        match &tree[0] {
+            Stmt::Var {
+                initializer: Some(Expr::Match { arms, .. }),
+                ..
+            } => assert!(matches!(arms[0].guard, Some(Expr::Grouping { .. }))),
+            other => panic!("expected a match expression, got {:?}", other),
+        }
+        // arrow functions nested inside of a guard aren't cut short.
+        for input in [
+            "var r = match ([1,2,3]) { l if any(l, (y) => y > 2) => \"big\", _ => \"small\" };",
+            "var r = match (v) { l if [(y) => y][0](l) => 1, _ => 2 };",
+            "var r = match (v) { l if {f: (y) => y}.f(l) => 1, _ => 2 };",
+            "var r = match (v) { l if (((y) => y)(l)) => 1, _ => 2 };",
+            "var r = match (v) { l if fun () { return (y) => y; }()(l) => 1, _ => 2 };",
+        ] {
+            match &parse(input).unwrap()[0] {
+                Stmt::Var {
+                    initializer: Some(Expr::Match { arms, .. }),
+                    ..
+                } => assert!(arms[0].guard.is_some() && arms.len() == 2, "{}", input),
+                other => panic!("expected a match expression, got {:?}", other),
+            }
+        }
+        let names: Vec<String> = arms[2]
+            .pattern
+            .bindings()
+            .iter()
+            .map(|n| n.lexeme_or_empty())
+            .collect();
+        assert_eq!(names, vec!["a"]);
+        assert!(matches!(arms[3].pattern, Pattern::Wildcard(_)));
+
+        for input in [
+            "match (v) { x | 1 => 1 };",
+            "match (v) { 1 2 };",
+            "match (v) { {\"a\"} => 1 };",
+        ] {
+            assert!(parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let parse = |input: &str| {
+            let tokens = Scanner::new(input).scan_tokens().unwrap();
+            Parser::new(tokens).parse()
+        };
+
+        let tree = parse("var [a, [b, _], ...c] = x; var {name, \"age\": n} = p; [a, b] = [b, a];")
+            .unwrap();
+        match &tree[0] {
+            Stmt::Destructure { pattern, .. } => assert!(matches!(
+                pattern.as_ref(),
+                Pattern::List { elements, rest: Some(_), .. } if elements.len() == 2
+            )),
+            other => panic!("expected a destructuring declaration, got {:?}", other),
+        }
+        match &tree[1] {
+            Stmt::Destructure { pattern, .. } => {
+                let names: Vec<String> = pattern
+                    .bindings()
+                    .iter()
+                    .map(|n| n.lexeme_or_empty())
+                    .collect();
+                assert_eq!(names, vec!["name", "n"]);
+            }
+            other => panic!("expected a destructuring declaration, got {:?}", other),
+        }
+        match &tree[2] {
+            Stmt::Expression {
+                expression: Expr::DestructureAssign { pattern, .. },
+            } => assert!(matches!(pattern.as_ref(), Pattern::List { .. })),
+            other => panic!("expected a destructuring assignment, got {:?}", other),
+        }
+
+        for input in ["[a, 1] = x;", "[a, b.c] = x;", "[a] += x;", "var [a, b];"] {
+            assert!(parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_import_forms() {
+        let input = "import \"util.lox\" as util; from \"util.lox\" import a, b;";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        match (&tree[0], &tree[1]) {
+            (
+                Stmt::Import {
+                    alias: Some(alias),
+                    names: first_names,
+                    ..
+                },
+                Stmt::Import {
+                    alias: None, names, ..
+                },
+            ) => {
+                assert_eq!(alias.lexeme_or_empty(), "util");
+                assert!(first_names.is_empty());
+                let names: Vec<String> = names.iter().map(|n| n.lexeme_or_empty()).collect();
+                assert_eq!(names, vec!["a", "b"]);
+            }
+            other => panic!("expected two import statements, got {:?}", other),
+        }
+
+        let tokens = Scanner::new("import \"util.lox\" util;")
+            .scan_tokens()
+            .unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_ternary_and_coalesce_precedence() {
+        let input = "a = b ?? c ? d : e ? f : g;";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        match &tree[0] {
+            Stmt::Expression {
+                expression: Expr::Assign { value, .. },
+            } => match value.as_ref() {
+                Expr::Ternary {
+                    condition,
+                    else_branch,
+                    ..
+                } => {
+                    assert!(matches!(condition.as_ref(), Expr::Logical { operator, .. }
+                        if operator.token_type == TokenType::QuestionQuestion));
+                    assert!(matches!(else_branch.as_ref(), Expr::Ternary { .. }));
+                }
+                other => panic!("expected a ternary, got {:?}", other),
+            },
+            other => panic!("expected an assignment, got {:?}", other),
+        }
+
+        let tokens = Scanner::new("a ? b;").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_generator_functions_are_marked() {
+        let input = "fun gen() { if (true) { yield 1; } }
+            fun plain() { var inner = fun () { yield 1; }; return inner; }
+            var arrow = () => { while (true) yield; };
+            var short = (x) => x;";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        let flags: Vec<bool> = tree
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Function { is_generator, .. } => *is_generator,
+                Stmt::Var {
+                    initializer: Some(Expr::Function { is_generator, .. }),
+                    ..
+                } => *is_generator,
+                other => panic!("expected a function, got {:?}", other),
+            })
+            .collect();
+        // a yield in a nested function doesn't make the outer one a generator.
+        assert_eq!(flags, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn test_double_slash_is_a_comment() {
+        let input = "var q = a // b\n~/ c;\na // b\n;";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        assert_eq!(tree.len(), 2);
+        match &tree[0] {
+            Stmt::Var {
+                initializer:
+                    Some(Expr::Binary {
+                        left,
+                        operator,
+                        right,
+                    }),
+                ..
+            } => {
+                assert_eq!(operator.token_type, TokenType::TildeSlash);
+                assert!(
+                    matches!(left.as_ref(), Expr::Variable { name } if name.lexeme_or_empty() == "a")
+                );
+                assert!(
+                    matches!(right.as_ref(), Expr::Variable { name } if name.lexeme_or_empty() == "c")
+                );
+            }
+            other => panic!("expected a floor division, got {:?}", other),
+        }
+        assert!(matches!(
+            &tree[1],
+            Stmt::Expression { expression: Expr::Variable { name } } if name.lexeme_or_empty() == "a"
+        ));
+    }
+
+    #[test]
+    fn test_doc_comments_attach_to_declarations() {
+        let input = "/// The answer.\nconst ANSWER = 42;\n/// Greets.\nfun greet() {}\nvar plain;";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        let tree = Parser::new(tokens).parse().unwrap();
+
+        let docs: Vec<Option<&str>> = tree.iter().map(|stmt| stmt.doc()).collect();
+        assert_eq!(docs, vec![Some("The answer."), Some("Greets."), None]);
+    }
 }
@@ -0,0 +1,576 @@
+use crate::language::ast::{Expr, MatchArm, Param, Stmt};
+use crate::language::errors::ParseError;
+use crate::language::token::{Slot, Token};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Binding {
+    Mutable,
+    Const,
+}
+
+fn slot(depth: usize, index: usize) -> Slot {
+    let narrow = |n: usize| u32::try_from(n).expect("too many nested scopes or variables");
+    Slot {
+        depth: narrow(depth),
+        index: narrow(index),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    // each name with the slot index it was given.
+    names: HashMap<String, (Binding, usize)>,
+}
+
+#[derive(Debug)]
+struct FunctionScope {
+    // the scope of its parameters.
+    base: usize,
+    // the variables it closes over, in the order they were first used.
+    captures: Vec<(String, Slot)>,
+}
+
+// a static pass over a parsed program that tracks lexical scopes. It gives
+// every local variable a slot (see Slot) and lists what each function
+// captures, rejects assignments to (and redeclarations of) const bindings and
+// misplaced yields. Names it can't see (globals defined later, natives...)
+// are left to the runtime.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    // the first scope is the top level of the script, whose names are
+    // looked up by symbol rather than given slots.
+    scopes: Vec<Scope>,
+    functions: Vec<FunctionScope>,
+    errors: Vec<ParseError>,
+    in_try: bool,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+            ..Self::default()
+        }
+    }
+
+    pub fn resolve(mut self, stmts: &mut [Stmt]) -> Result<(), Vec<ParseError>> {
+        self.statements(stmts);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn statements(&mut self, stmts: &mut [Stmt]) {
+        // local functions are visible to the whole block, so they can call
+        // the ones declared after them (names at the top level are looked up
+        // as the program runs anyway).
+        if self.scopes.len() > 1 {
+            for stmt in stmts.iter_mut() {
+                let scope = self.scopes.last().expect("resolver always has a scope");
+                match stmt {
+                    Stmt::Function { name, .. }
+                        if !scope.names.contains_key(&name.lexeme_or_empty()) =>
+                    {
+                        self.declare(name, Binding::Mutable)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn scoped(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Scope::default());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &mut Token, binding: Binding) {
+        let is_global = self.scopes.len() == 1;
+        let scope = self.scopes.last_mut().expect("resolver always has a scope");
+        let lexeme = name.lexeme_or_empty();
+        let index = match scope.names.get(&lexeme) {
+            Some((Binding::Const, _)) => {
+                self.errors.push(ParseError::ConstReassignment {
+                    token_lexeme: lexeme,
+                    coordinate: name.coordinate.clone(),
+                });
+                return;
+            }
+            // a redeclaration reuses the slot.
+            Some((_, index)) => *index,
+            None => scope.names.len(),
+        };
+        scope.names.insert(lexeme, (binding, index));
+        if !is_global {
+            name.slot = Some(slot(0, index));
+        }
+    }
+
+    fn assign(&mut self, name: &mut Token) {
+        let lexeme = name.lexeme_or_empty();
+        let binding = self.scopes.iter().rev().find_map(|s| s.names.get(&lexeme));
+        if let Some((Binding::Const, _)) = binding {
+            self.errors.push(ParseError::ConstReassignment {
+                token_lexeme: lexeme,
+                coordinate: name.coordinate.clone(),
+            });
+        }
+        self.reference(name);
+    }
+
+    fn reference(&mut self, name: &mut Token) {
+        name.slot = self.lookup(self.functions.len(), &name.lexeme_or_empty());
+    }
+
+    // the scopes [base, end) belong to the code of a function (level > 0)
+    // or, at level 0, to the top level of the script, minus its first scope.
+    fn bounds(&self, level: usize) -> (usize, usize) {
+        let base = match level {
+            0 => 1,
+            _ => self.functions[level - 1].base,
+        };
+        let end = match self.functions.get(level) {
+            Some(function) => function.base,
+            None => self.scopes.len(),
+        };
+        (base, end)
+    }
+
+    // where the name is found from the innermost scope of the given level, a
+    // variable from outside of a function is captured (by every function in
+    // between) and reached through its closure, one scope out from its
+    // parameters. None means it's a global.
+    fn lookup(&mut self, level: usize, name: &str) -> Option<Slot> {
+        let (base, end) = self.bounds(level);
+        for depth in 0..end - base {
+            if let Some((_, index)) = self.scopes[end - 1 - depth].names.get(name) {
+                return Some(slot(depth, *index));
+            }
+        }
+        if level == 0 {
+            return None;
+        }
+
+        let captures = &self.functions[level - 1].captures;
+        let index = match captures.iter().position(|(captured, _)| captured == name) {
+            Some(index) => index,
+            None => {
+                let outer = self.lookup(level - 1, name)?;
+                let captures = &mut self.functions[level - 1].captures;
+                captures.push((name.to_string(), outer));
+                captures.len() - 1
+            }
+        };
+        Some(slot(end - base, index))
+    }
+
+    // resolves a function and returns what it captures.
+    fn function(&mut self, params: &mut [Param], body: &mut Rc<[Stmt]>) -> Vec<Slot> {
+        let body = Rc::get_mut(body).expect("functions are resolved before they're shared");
+        let enclosing = std::mem::replace(&mut self.in_try, false);
+        self.functions.push(FunctionScope {
+            base: self.scopes.len(),
+            captures: Vec::new(),
+        });
+        self.scoped(|r| {
+            for param in params {
+                if let Some(default) = &mut param.default {
+                    r.expression(default);
+                }
+                r.declare(&mut param.name, Binding::Mutable);
+            }
+            r.statements(body);
+        });
+        self.in_try = enclosing;
+        let function = self.functions.pop().expect("pushed above");
+        function
+            .captures
+            .into_iter()
+            .map(|(_, slot)| slot)
+            .collect()
+    }
+
+    // generators suspend between statements, which try can't survive.
+    fn check_yield(&mut self, keyword: &Token) {
+        let msg = if self.functions.is_empty() {
+            "\"yield\" can only occur inside a function"
+        } else if self.in_try {
+            "\"yield\" can't occur inside of try, catch or finally"
+        } else {
+            return;
+        };
+        self.errors.push(ParseError::InvalidYield {
+            msg,
+            coordinate: keyword.coordinate.clone(),
+        });
+    }
+
+    fn match_arms(&mut self, arms: &mut [MatchArm]) {
+        for i in 0..arms.len() {
+            let arm = &arms[i];
+            // a guarded arm can fall through, so it never shadows later ones.
+            let shadowed = arms[..i]
+                .iter()
+                .any(|earlier| earlier.guard.is_none() && earlier.pattern.covers(&arm.pattern));
+            if shadowed {
+                self.errors.push(ParseError::UnreachableArm {
+                    coordinate: arm.pattern.token().coordinate.clone(),
+                });
+            }
+
+            let arm = &mut arms[i];
+            self.scoped(|r| {
+                for name in arm.pattern.bindings_mut() {
+                    r.declare(name, Binding::Mutable);
+                }
+                if let Some(guard) = &mut arm.guard {
+                    r.expression(guard);
+                }
+                r.expression(&mut arm.body);
+            });
+        }
+    }
+
+    fn statement(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Expression { expression } | Stmt::Print { expression } => {
+                self.expression(expression)
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.declare(name, Binding::Mutable);
+            }
+            Stmt::Destructure {
+                pattern,
+                initializer,
+                ..
+            } => {
+                self.expression(initializer);
+                for name in pattern.bindings_mut() {
+                    self.declare(name, Binding::Mutable);
+                }
+            }
+            Stmt::Const {
+                name, initializer, ..
+            } => {
+                self.expression(initializer);
+                self.declare(name, Binding::Const);
+            }
+            Stmt::Block { statements } => self.scoped(|r| r.statements(statements)),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::While { condition, body } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Stmt::For {
+                initializer,
+                condition,
+                increment,
+                body,
+            } => self.scoped(|r| {
+                if let Some(initializer) = initializer {
+                    r.statement(initializer);
+                }
+                r.expression(condition);
+                if let Some(increment) = increment {
+                    r.expression(increment);
+                }
+                r.statement(body);
+            }),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expression(iterable);
+                self.scoped(|r| {
+                    r.declare(name, Binding::Mutable);
+                    r.statement(body);
+                });
+            }
+            Stmt::Break { .. } => {}
+            Stmt::Function {
+                name,
+                params,
+                body,
+                captures,
+                ..
+            } => {
+                self.declare(name, Binding::Mutable);
+                *captures = self.function(params, body);
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Stmt::Yield {
+                keyword,
+                value,
+                target,
+                ..
+            } => {
+                self.check_yield(keyword);
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                if let Some(target) = target {
+                    self.declare(target, Binding::Mutable);
+                }
+            }
+            Stmt::Throw { value, .. } => self.expression(value),
+            Stmt::Import { alias, names, .. } => {
+                for name in alias.iter_mut().chain(names) {
+                    self.declare(name, Binding::Mutable);
+                }
+            }
+            Stmt::Try {
+                body,
+                catch_name,
+                catch_body,
+                finally_body,
+                ..
+            } => {
+                let enclosing = std::mem::replace(&mut self.in_try, true);
+                self.scoped(|r| r.statements(body));
+                if let Some(catch_body) = catch_body {
+                    self.scoped(|r| {
+                        if let Some(name) = catch_name {
+                            r.declare(name, Binding::Mutable);
+                        }
+                        r.statements(catch_body);
+                    });
+                }
+                if let Some(finally_body) = finally_body {
+                    self.scoped(|r| r.statements(finally_body));
+                }
+                self.in_try = enclosing;
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Literal { .. } => {}
+            Expr::Variable { name } => self.reference(name),
+            Expr::Grouping { expression } => self.expression(expression),
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expr::Match { subject, arms, .. } => {
+                self.expression(subject);
+                self.match_arms(arms);
+            }
+            Expr::Unary { right, .. } => self.expression(right),
+            Expr::Call { callee, args, .. } => {
+                self.expression(callee);
+                args.iter_mut().for_each(|arg| self.expression(arg));
+            }
+            Expr::DestructureAssign { pattern, value, .. } => {
+                self.expression(value);
+                for name in pattern.bindings_mut() {
+                    self.assign(name);
+                }
+            }
+            Expr::Assign { name, value } => {
+                self.expression(value);
+                self.assign(name);
+            }
+            Expr::List { elements, .. } => elements.iter_mut().for_each(|e| self.expression(e)),
+            Expr::Index { object, index, .. } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+            }
+            Expr::Get { object, .. } | Expr::SafeGet { object, .. } => self.expression(object),
+            Expr::Set { object, value, .. } => {
+                self.expression(object);
+                self.expression(value);
+            }
+            Expr::Map { entries, .. } => entries.iter_mut().for_each(|(_, e)| self.expression(e)),
+            Expr::Function {
+                params,
+                body,
+                captures,
+                ..
+            } => *captures = self.function(params, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::parse as resolve;
+
+    #[test]
+    fn test_const_reassignment() {
+        assert!(resolve("const a = 1; print a;").is_ok());
+
+        for input in [
+            "const a = 1; a = 2;",
+            "const a = 1; a += 2;",
+            "const a = 1; var a = 2;",
+            "const a = 1; fun f() { a = 2; }",
+            "{ const a = 1; { a = 2; } }",
+        ] {
+            let errors = resolve(input).unwrap_err();
+            assert!(
+                matches!(errors[0], ParseError::ConstReassignment { .. }),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_unreachable_match_arms() {
+        for input in [
+            "match (v) { _ => 1, 2 => 2 };",
+            "match (v) { x => 1, [a] => 2 };",
+            "match (v) { 1 | 2 => 1, 2 => 2 };",
+            "match (v) { [a, ...r] => 1, [1, 2] => 2 };",
+            "match (v) { {a} => 1, {a: 1, b} => 2 };",
+        ] {
+            let errors = resolve(input).unwrap_err();
+            assert!(
+                matches!(errors[0], ParseError::UnreachableArm { .. }),
+                "{}",
+                input
+            );
+        }
+
+        assert!(resolve("match (v) { x if x > 1 => 1, 2 => 2, _ => 3 };").is_ok());
+        assert!(resolve("match (v) { [a] => 1, [a, ...r] => 2 };").is_ok());
+        assert!(resolve("const c = 1; match (v) { c => c = 2 };").is_ok());
+    }
+
+    #[test]
+    fn test_destructuring_a_const() {
+        assert!(resolve("const a = 1; [a, b] = [2, 3];").is_err());
+        assert!(resolve("const a = 1; var [a] = [2];").is_err());
+        assert!(resolve("const a = 1; { var [a] = [2]; a = 3; }").is_ok());
+    }
+
+    #[test]
+    fn test_yield_placement() {
+        assert!(resolve("fun gen() { while (true) { yield 1; } }").is_ok());
+        assert!(resolve("fun gen() { try { var f = fun () { yield 1; }; } catch (e) {} }").is_ok());
+
+        for input in [
+            "yield 1;",
+            "fun gen() { try { yield 1; } catch (e) {} }",
+            "fun gen() { try {} finally { yield; } }",
+        ] {
+            let errors = resolve(input).unwrap_err();
+            assert!(
+                matches!(errors[0], ParseError::InvalidYield { .. }),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_slots_and_captures() {
+        let stmts = resolve("var g; fun f(a) { var b; fun h() { return a + b + g; } }").unwrap();
+        let Stmt::Function { name, body, .. } = &stmts[1] else {
+            panic!("expected a function");
+        };
+        assert_eq!(name.slot, None);
+        let Stmt::Function {
+            name,
+            body,
+            captures,
+            ..
+        } = &body[1]
+        else {
+            panic!("expected a function");
+        };
+        // h is hoisted, so it's given a slot before b.
+        assert_eq!(name.slot, Some(Slot { depth: 0, index: 1 }));
+        assert_eq!(
+            captures,
+            &[Slot { depth: 0, index: 0 }, Slot { depth: 0, index: 2 }]
+        );
+
+        // a and b are reached through the closure, one scope out.
+        let mut names = Vec::new();
+        let Stmt::Return {
+            value: Some(value), ..
+        } = &body[0]
+        else {
+            panic!("expected a return");
+        };
+        let mut expr: &Expr = value;
+        while let Expr::Binary { left, right, .. } = expr {
+            names.push(right.as_ref());
+            expr = left;
+        }
+        names.push(expr);
+        let slots: Vec<_> = names
+            .iter()
+            .map(|e| match e {
+                Expr::Variable { name } => name.slot,
+                _ => panic!("expected a variable"),
+            })
+            .collect();
+        assert_eq!(
+            slots,
+            [
+                None,
+                Some(Slot { depth: 1, index: 1 }),
+                Some(Slot { depth: 1, index: 0 })
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shadowing_a_const() {
+        assert!(resolve("const a = 1; { var a = 2; a = 3; }").is_ok());
+        assert!(resolve("const a = 1; fun f(a) { a = 2; }").is_ok());
+        assert!(resolve("{ const a = 1; } var a = 1; a = 2;").is_ok());
+    }
+}
@@ -43,18 +43,22 @@ pub enum LexicalError {
     InvalidCharacter(char, Coordinate),
     InvalidNumber(Coordinate),
     UnterminatedString(Coordinate),
+    UnterminatedComment(Coordinate),
     UnexpectedEndOfFile,
 }
 
 #[derive(Debug, Clone)]
 pub struct Scanner<'a> {
     chars: Peekable<CharWithCoordinate<'a>>,
+    // "///" comments waiting to be attached to the next token.
+    doc: Option<String>,
 }
 
 impl<'a> Scanner<'a> {
     pub fn new(src: &'a str) -> Scanner<'a> {
         Scanner {
             chars: CharWithCoordinate::new(src).peekable(),
+            doc: None,
         }
     }
 
@@ -63,7 +67,8 @@ impl<'a> Scanner<'a> {
 
         while !self.is_at_end() {
             let token = self.scan_token()?;
-            if let Some(toke) = token {
+            if let Some(mut toke) = token {
+                toke.doc = self.doc.take();
                 tokens.push(toke);
             }
         }
@@ -72,7 +77,7 @@ impl<'a> Scanner<'a> {
             if t.token_type != TokenType::Eof {
                 tokens.push(Token::new(
                     TokenType::Eof,
-                    None,
+                    Some(String::new()),
                     Literal::Nil,
                     t.coordinate.clone(),
                 ));
@@ -90,12 +95,39 @@ impl<'a> Scanner<'a> {
             ')' => Ok(self.simple_token(TokenType::RightParen, (ch, coordinate))),
             '{' => Ok(self.simple_token(TokenType::LeftBrace, (ch, coordinate))),
             '}' => Ok(self.simple_token(TokenType::RightBrace, (ch, coordinate))),
+            '[' => Ok(self.simple_token(TokenType::LeftBracket, (ch, coordinate))),
+            ']' => Ok(self.simple_token(TokenType::RightBracket, (ch, coordinate))),
             ',' => Ok(self.simple_token(TokenType::Comma, (ch, coordinate))),
+            ':' => Ok(self.simple_token(TokenType::Colon, (ch, coordinate))),
+            // note that "a?.5:b" scans as "a ?. 5 : b", write "a ? .5 : b" or "a?0.5:b".
+            '?' => {
+                let toke = if self.match_char('?') {
+                    self.multi_char_token(TokenType::QuestionQuestion, "??".to_string(), coordinate)
+                } else if self.match_char('.') {
+                    self.multi_char_token(TokenType::QuestionDot, "?.".to_string(), coordinate)
+                } else {
+                    self.simple_token(TokenType::Question, (ch, coordinate))
+                };
+                Ok(toke)
+            }
             '.' => {
                 if self.next_is_digit() {
                     return self.number(String::from('.'), coordinate);
                 }
 
+                if let Some(&('.', ref second)) = self.peek() {
+                    let second = second.clone();
+                    self.take()?;
+                    if !self.match_char('.') {
+                        return Err(LexicalError::InvalidCharacter('.', second));
+                    }
+                    return Ok(self.multi_char_token(
+                        TokenType::Ellipsis,
+                        "...".to_string(),
+                        coordinate,
+                    ));
+                }
+
                 Ok(self.simple_token(TokenType::Dot, (ch, coordinate)))
             }
             '-' => {
@@ -116,13 +148,45 @@ impl<'a> Scanner<'a> {
             }
             ';' => Ok(self.simple_token(TokenType::Semicolon, (ch, coordinate))),
             '*' => {
-                let toke = if self.match_char('=') {
+                let toke = if self.match_char('*') {
+                    if self.match_char('=') {
+                        self.multi_char_token(
+                            TokenType::StarStarEqual,
+                            "**=".to_string(),
+                            coordinate,
+                        )
+                    } else {
+                        self.multi_char_token(TokenType::StarStar, "**".to_string(), coordinate)
+                    }
+                } else if self.match_char('=') {
                     self.multi_char_token(TokenType::StarEqual, "*=".to_string(), coordinate)
                 } else {
-                    self.simple_token(TokenType::Plus, (ch, coordinate))
+                    self.simple_token(TokenType::Star, (ch, coordinate))
                 };
                 Ok(toke)
             }
+            '%' => Ok(self.with_equal(TokenType::Percent, TokenType::PercentEqual, ch, coordinate)),
+            '&' => Ok(self.with_equal(
+                TokenType::Ampersand,
+                TokenType::AmpersandEqual,
+                ch,
+                coordinate,
+            )),
+            '|' => Ok(self.with_equal(TokenType::Pipe, TokenType::PipeEqual, ch, coordinate)),
+            '^' => Ok(self.with_equal(TokenType::Caret, TokenType::CaretEqual, ch, coordinate)),
+            // "~/" is floor division, "//" always starts a comment.
+            '~' if self.match_char('/') => {
+                if self.match_char('=') {
+                    Ok(self.multi_char_token(
+                        TokenType::TildeSlashEqual,
+                        "~/=".to_string(),
+                        coordinate,
+                    ))
+                } else {
+                    Ok(self.multi_char_token(TokenType::TildeSlash, "~/".to_string(), coordinate))
+                }
+            }
+            '~' => Ok(self.simple_token(TokenType::Tilde, (ch, coordinate))),
             '!' => {
                 let toke = if self.match_char('=') {
                     self.multi_char_token(TokenType::BangEqual, "!=".to_string(), coordinate)
@@ -134,13 +198,25 @@ impl<'a> Scanner<'a> {
             '=' => {
                 let toke = if self.match_char('=') {
                     self.multi_char_token(TokenType::EqualEqual, "==".to_string(), coordinate)
+                } else if self.match_char('>') {
+                    self.multi_char_token(TokenType::Arrow, "=>".to_string(), coordinate)
                 } else {
                     self.simple_token(TokenType::Equal, (ch, coordinate))
                 };
                 Ok(toke)
             }
             '<' => {
-                let toke = if self.match_char('=') {
+                let toke = if self.match_char('<') {
+                    if self.match_char('=') {
+                        self.multi_char_token(
+                            TokenType::LessLessEqual,
+                            "<<=".to_string(),
+                            coordinate,
+                        )
+                    } else {
+                        self.multi_char_token(TokenType::LessLess, "<<".to_string(), coordinate)
+                    }
+                } else if self.match_char('=') {
                     self.multi_char_token(TokenType::LessEqual, "<=".to_string(), coordinate)
                 } else {
                     self.simple_token(TokenType::Less, (ch, coordinate))
@@ -148,7 +224,21 @@ impl<'a> Scanner<'a> {
                 Ok(toke)
             }
             '>' => {
-                let toke = if self.match_char('=') {
+                let toke = if self.match_char('>') {
+                    if self.match_char('=') {
+                        self.multi_char_token(
+                            TokenType::GreaterGreaterEqual,
+                            ">>=".to_string(),
+                            coordinate,
+                        )
+                    } else {
+                        self.multi_char_token(
+                            TokenType::GreaterGreater,
+                            ">>".to_string(),
+                            coordinate,
+                        )
+                    }
+                } else if self.match_char('=') {
                     self.multi_char_token(TokenType::GreaterEqual, ">=".to_string(), coordinate)
                 } else {
                     self.simple_token(TokenType::Greater, (ch, coordinate))
@@ -157,7 +247,17 @@ impl<'a> Scanner<'a> {
             }
             '/' => {
                 if self.match_char('/') {
-                    let _ = self.skip_comment();
+                    let text = self.skip_comment();
+                    // "///" is a doc comment, while "////..." is just a comment.
+                    if let Some(doc) = text.strip_prefix('/').filter(|t| !t.starts_with('/')) {
+                        self.push_doc(doc);
+                    }
+                    if self.is_at_end() {
+                        return Ok(None);
+                    }
+                    Ok(self.scan_token()?)
+                } else if self.match_char('*') {
+                    self.skip_block_comment(coordinate)?;
                     if self.is_at_end() {
                         return Ok(None);
                     }
@@ -170,14 +270,14 @@ impl<'a> Scanner<'a> {
             }
             ' ' | '\r' | '\t' | '\n' => {
                 if self.is_at_end() {
-                    return Ok(None);
+                    Ok(None)
                 } else {
-                    return Ok(self.scan_token()?);
+                    self.scan_token()
                 }
             }
             '"' => self.string(String::from('"'), coordinate),
             _ => {
-                if ch.is_digit(10) {
+                if ch.is_ascii_digit() {
                     self.number(ch.to_string(), coordinate)
                 } else if ch.is_alphabetic() || ch == '_' {
                     self.identifier(ch.to_string(), coordinate)
@@ -213,24 +313,133 @@ impl<'a> Scanner<'a> {
         mut lexeme: String,
         start_coordinate: Coordinate,
     ) -> Result<Option<Token>, LexicalError> {
-        while self.next_is_digit() {
-            let (ch, _) = self.take()?;
-            lexeme.push(ch);
+        // the literal without its "_" separators, which is what rust parses.
+        let mut clean = lexeme.clone();
+
+        if lexeme == "0" {
+            if let Some(radix) = self.radix_prefix(&mut lexeme)? {
+                clean.clear();
+                self.required_digits(&mut lexeme, &mut clean, radix)?;
+                let value = i64::from_str_radix(&clean, radix)
+                    .map_err(|_| LexicalError::InvalidNumber(start_coordinate.clone()))?;
+                return self.number_token(lexeme, Literal::Integer(value), start_coordinate);
+            }
         }
 
-        if self.match_char('.') {
+        self.digits(&mut lexeme, &mut clean, 10)?;
+        let mut is_integer = !lexeme.starts_with('.');
+
+        if let Some(&('.', ref coordinate)) = self.peek() {
+            let dot = coordinate.clone();
+            self.take()?;
             if !self.next_is_digit() {
-                return Err(LexicalError::InvalidNumber(start_coordinate));
+                return Err(LexicalError::InvalidNumber(dot));
             }
-
+            is_integer = false;
             lexeme.push('.');
-            while self.next_is_digit() {
-                let (ch, _) = self.take()?;
+            clean.push('.');
+            self.digits(&mut lexeme, &mut clean, 10)?;
+        }
+
+        if let Some(&('e' | 'E', _)) = self.peek() {
+            is_integer = false;
+            let (e, _) = self.take()?;
+            lexeme.push(e);
+            clean.push(e);
+            if let Some(&(sign @ ('+' | '-'), _)) = self.peek() {
+                self.take()?;
+                lexeme.push(sign);
+                clean.push(sign);
+            }
+            self.required_digits(&mut lexeme, &mut clean, 10)?;
+        }
+
+        // literals without a fraction or exponent are integers, which must fit in an i64.
+        let literal = if is_integer {
+            Literal::Integer(
+                clean
+                    .parse::<i64>()
+                    .map_err(|_| LexicalError::InvalidNumber(start_coordinate.clone()))?,
+            )
+        } else {
+            Literal::Number(self.parse_number(&clean, start_coordinate.clone())?)
+        };
+
+        self.number_token(lexeme, literal, start_coordinate)
+    }
+
+    // consumes the "x", "b" or "o" of "0x", "0b" and "0o" literals.
+    fn radix_prefix(&mut self, lexeme: &mut String) -> Result<Option<u32>, LexicalError> {
+        let radix = match self.peek() {
+            Some(&('x' | 'X', _)) => 16,
+            Some(&('b' | 'B', _)) => 2,
+            Some(&('o' | 'O', _)) => 8,
+            _ => return Ok(None),
+        };
+        let (prefix, _) = self.take()?;
+        lexeme.push(prefix);
+        Ok(Some(radix))
+    }
+
+    // consumes digits of the given radix, a "_" is only allowed between two digits.
+    fn digits(
+        &mut self,
+        lexeme: &mut String,
+        clean: &mut String,
+        radix: u32,
+    ) -> Result<usize, LexicalError> {
+        let mut count = 0;
+        while let Some(&(ch, ref coordinate)) = self.peek() {
+            if ch == '_' {
+                let separator = coordinate.clone();
+                let after_digit = clean.chars().last().is_some_and(|c| c.is_digit(radix));
+                self.take()?;
+                if !after_digit || !self.next_is_digit_of(radix) {
+                    return Err(LexicalError::InvalidNumber(separator));
+                }
                 lexeme.push(ch);
+            } else if ch.is_digit(radix) {
+                self.take()?;
+                lexeme.push(ch);
+                clean.push(ch);
+                count += 1;
+            } else {
+                break;
             }
         }
+        Ok(count)
+    }
+
+    // like digits, but there has to be at least one, the error points at
+    // whatever is there instead.
+    fn required_digits(
+        &mut self,
+        lexeme: &mut String,
+        clean: &mut String,
+        radix: u32,
+    ) -> Result<(), LexicalError> {
+        if self.digits(lexeme, clean, radix)? > 0 {
+            return Ok(());
+        }
+        match self.peek() {
+            Some((_, coordinate)) => Err(LexicalError::InvalidNumber(coordinate.clone())),
+            None => Err(LexicalError::UnexpectedEndOfFile),
+        }
+    }
 
-        let literal = Literal::Number(self.parse_number(&lexeme, start_coordinate.clone())?);
+    // a digit that doesn't belong to the literal's radix (the "2" of "0b102")
+    // is an error pointing at it, letters start a new token as before.
+    fn number_token(
+        &mut self,
+        lexeme: String,
+        literal: Literal,
+        start_coordinate: Coordinate,
+    ) -> Result<Option<Token>, LexicalError> {
+        if let Some(&(ch, ref coordinate)) = self.peek() {
+            if ch.is_ascii_digit() {
+                return Err(LexicalError::InvalidNumber(coordinate.clone()));
+            }
+        }
 
         Ok(Some(Token::new(
             TokenType::Number,
@@ -270,6 +479,16 @@ impl<'a> Scanner<'a> {
             "var" => TokenType::Var,
             "while" => TokenType::While,
             "break" => TokenType::Break,
+            "throw" => TokenType::Throw,
+            "try" => TokenType::Try,
+            "catch" => TokenType::Catch,
+            "finally" => TokenType::Finally,
+            "import" => TokenType::Import,
+            "const" => TokenType::Const,
+            "from" => TokenType::From,
+            "match" => TokenType::Match,
+            "yield" => TokenType::Yield,
+            "in" => TokenType::In,
             _ => TokenType::Identifier,
         };
 
@@ -292,12 +511,47 @@ impl<'a> Scanner<'a> {
             .map_err(|_| LexicalError::InvalidNumber(coordinate))
     }
 
-    fn skip_comment(&mut self) {
+    // skips the rest of a line comment, returning its text.
+    fn skip_comment(&mut self) -> String {
+        let mut text = String::new();
         while let Ok((ch, _)) = self.take() {
-            if ch == '\n' || self.is_at_end() {
-                return;
+            if ch == '\n' {
+                break;
+            }
+            text.push(ch);
+            if self.is_at_end() {
+                break;
             }
         }
+        text
+    }
+
+    fn push_doc(&mut self, line: &str) {
+        let line = line.strip_prefix(' ').unwrap_or(line).trim_end();
+        match self.doc {
+            Some(ref mut doc) => {
+                doc.push('\n');
+                doc.push_str(line);
+            }
+            None => self.doc = Some(line.to_string()),
+        }
+    }
+
+    // block comments nest, so "/* a /* b */ c */" is a single comment. The
+    // opening "/*" has already been consumed.
+    fn skip_block_comment(&mut self, start: Coordinate) -> Result<(), LexicalError> {
+        let mut depth = 1;
+        while depth > 0 {
+            let (ch, _) = self
+                .take()
+                .map_err(|_| LexicalError::UnterminatedComment(start.clone()))?;
+            if ch == '/' && self.match_char('*') {
+                depth += 1;
+            } else if ch == '*' && self.match_char('/') {
+                depth -= 1;
+            }
+        }
+        Ok(())
     }
 
     fn multi_char_token(
@@ -322,9 +576,28 @@ impl<'a> Scanner<'a> {
         self.chars.peek().is_none()
     }
 
+    // for operators that have a compound assignment form, e.g. "%" and "%=".
+    fn with_equal(
+        &mut self,
+        plain: TokenType,
+        compound: TokenType,
+        ch: char,
+        coordinate: Coordinate,
+    ) -> Option<Token> {
+        if self.match_char('=') {
+            self.multi_char_token(compound, format!("{}=", ch), coordinate)
+        } else {
+            self.simple_token(plain, (ch, coordinate))
+        }
+    }
+
     fn next_is_digit(&mut self) -> bool {
+        self.next_is_digit_of(10)
+    }
+
+    fn next_is_digit_of(&mut self, radix: u32) -> bool {
         if let Some(&(ch, _)) = self.peek() {
-            return ch.is_digit(10);
+            return ch.is_digit(radix);
         }
         false
     }
@@ -396,7 +669,7 @@ mod test {
 
     #[test]
     fn test_single_character_tokens() {
-        let input = "(){},.-+;*!";
+        let input = "(){},.-+;*![]";
         let scanner = Scanner::new(input);
         let tokens = scanner.scan_tokens().unwrap();
 
@@ -412,7 +685,9 @@ mod test {
             make_token(TokenType::Semicolon, ";", Literal::Nil, 8, 1, 9),
             make_token(TokenType::Star, "*", Literal::Nil, 9, 1, 10),
             make_token(TokenType::Bang, "!", Literal::Nil, 10, 1, 11),
-            make_token(TokenType::Eof, "", Literal::Nil, 10, 1, 11),
+            make_token(TokenType::LeftBracket, "[", Literal::Nil, 11, 1, 12),
+            make_token(TokenType::RightBracket, "]", Literal::Nil, 12, 1, 13),
+            make_token(TokenType::Eof, "", Literal::Nil, 12, 1, 13),
         ];
 
         assert_eq!(tokens, expected_tokens);
@@ -450,16 +725,19 @@ mod test {
             let result = scanner.scan_tokens();
 
             if input.ends_with('.') {
-                // Expect an error
+                // Expect an error, pointing at the dot
                 assert!(result.is_err());
                 assert_eq!(
                     result.unwrap_err(),
-                    LexicalError::InvalidNumber(Coordinate::new(index, line, column))
+                    LexicalError::InvalidNumber(Coordinate::new(index + 2, line, column + 2))
                 );
             } else {
                 let tokens = result.unwrap();
                 assert_eq!(tokens.len(), 2);
-                let expected_literal = Literal::Number(input.parse::<f64>().unwrap());
+                let expected_literal = match input.parse::<i64>() {
+                    Ok(i) => Literal::Integer(i),
+                    Err(_) => Literal::Number(input.parse::<f64>().unwrap()),
+                };
                 let expected_token = make_token(
                     TokenType::Number,
                     input,
@@ -599,7 +877,7 @@ mod test {
         let tokens = result.unwrap();
 
         // For brevity, we'll just check that certain tokens are present
-        let token_types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        let token_types: Vec<TokenType> = tokens.iter().map(|t| t.token_type).collect();
 
         let expected_token_types = vec![
             TokenType::Class,
@@ -689,4 +967,126 @@ mod test {
 
         assert_eq!(token_types, expected_token_types);
     }
+
+    #[test]
+    fn test_floor_division_and_comments() {
+        let types = |input| -> Vec<TokenType> {
+            Scanner::new(input)
+                .scan_tokens()
+                .unwrap()
+                .iter()
+                .map(|t| t.token_type)
+                .collect()
+        };
+
+        assert_eq!(
+            types("a ~/ b; x ~/= 2; ~x;"),
+            vec![
+                TokenType::Identifier,
+                TokenType::TildeSlash,
+                TokenType::Identifier,
+                TokenType::Semicolon,
+                TokenType::Identifier,
+                TokenType::TildeSlashEqual,
+                TokenType::Number,
+                TokenType::Semicolon,
+                TokenType::Tilde,
+                TokenType::Identifier,
+                TokenType::Semicolon,
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(
+            types("a; // comment\n// b\nb"),
+            vec![
+                TokenType::Identifier,
+                TokenType::Semicolon,
+                TokenType::Identifier,
+                TokenType::Eof,
+            ]
+        );
+        // trailing comments right after an operand.
+        assert_eq!(
+            types("f(x) // call\ny // name\n7 // 2"),
+            vec![
+                TokenType::Identifier,
+                TokenType::LeftParen,
+                TokenType::Identifier,
+                TokenType::RightParen,
+                TokenType::Identifier,
+                TokenType::Number,
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(
+            types("** **= % & |= ^ ~ << >>="),
+            vec![
+                TokenType::StarStar,
+                TokenType::StarStarEqual,
+                TokenType::Percent,
+                TokenType::Ampersand,
+                TokenType::PipeEqual,
+                TokenType::Caret,
+                TokenType::Tilde,
+                TokenType::LessLess,
+                TokenType::GreaterGreaterEqual,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_number_literal_forms() {
+        let literal = |input: &str| {
+            Scanner::new(input).scan_tokens().unwrap()[0]
+                .literal
+                .clone()
+        };
+
+        assert_eq!(literal("0xFF"), Literal::Integer(255));
+        assert_eq!(literal("0b1010"), Literal::Integer(10));
+        assert_eq!(literal("0o17"), Literal::Integer(15));
+        assert_eq!(literal("1_000_000"), Literal::Integer(1_000_000));
+        assert_eq!(literal("6.02e23"), Literal::Number(6.02e23));
+        assert_eq!(literal("1e-9"), Literal::Number(1e-9));
+        assert_eq!(literal("1_0.2_5E+1"), Literal::Number(102.5));
+
+        let error_column = |input: &str| match Scanner::new(input).scan_tokens() {
+            Err(LexicalError::InvalidNumber(coordinate)) => coordinate.column,
+            other => panic!("expected an invalid number for {}, got {:?}", input, other),
+        };
+
+        assert_eq!(error_column("0b102"), 5);
+        assert_eq!(error_column("0o19"), 4);
+        assert_eq!(error_column("0x;"), 3);
+        assert_eq!(error_column("0x_ff"), 3);
+        assert_eq!(error_column("1__000"), 2);
+        assert_eq!(error_column("1000_;"), 5);
+        assert_eq!(error_column("1e+;"), 4);
+        assert_eq!(error_column("0x8000000000000000"), 1);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let tokens = Scanner::new("a /* one /* nested */ still\n comment */ b")
+            .scan_tokens()
+            .unwrap();
+        let lexemes: Vec<String> = tokens.iter().map(|t| t.lexeme_or_empty()).collect();
+        assert_eq!(lexemes, vec!["a", "b", ""]);
+
+        assert_eq!(
+            Scanner::new("a;\n  /* open /* */").scan_tokens(),
+            Err(LexicalError::UnterminatedComment(Coordinate::new(5, 2, 3)))
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let input =
+            "/// Adds things.\n///   Indented.\n//// not docs\nfun add() {}\n// plain\nvar x;";
+        let tokens = Scanner::new(input).scan_tokens().unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::Fun);
+        assert_eq!(tokens[0].doc.as_deref(), Some("Adds things.\n  Indented."));
+        assert!(tokens.iter().skip(1).all(|t| t.doc.is_none()));
+    }
 }
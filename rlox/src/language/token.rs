@@ -1,3 +1,4 @@
+use crate::language::symbol::Symbol;
 use std::fmt;
 
 #[derive(Clone, Debug, PartialEq, Copy)]
@@ -7,8 +8,15 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
+    Ellipsis,
+    Colon,
+    Question,
+    QuestionQuestion,
+    QuestionDot,
 
     // One or two character tokens.
     Minus,
@@ -18,16 +26,36 @@ pub enum TokenType {
     Semicolon,
     Slash,
     SlashEqual,
+    // floor division is "~/" (as in dart) rather than python's "//", which
+    // already starts a comment.
+    TildeSlash,
+    TildeSlashEqual,
     Star,
     StarEqual,
+    StarStar,
+    StarStarEqual,
+    Percent,
+    PercentEqual,
+    Ampersand,
+    AmpersandEqual,
+    Pipe,
+    PipeEqual,
+    Caret,
+    CaretEqual,
+    Tilde,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    LessLessEqual,
+    GreaterGreater,
+    GreaterGreaterEqual,
 
     // Literals.
     Identifier,
@@ -52,6 +80,16 @@ pub enum TokenType {
     Var,
     While,
     Break,
+    Throw,
+    Try,
+    Catch,
+    Finally,
+    Import,
+    Const,
+    From,
+    Match,
+    Yield,
+    In,
 
     // End of file
     Eof,
@@ -59,6 +97,7 @@ pub enum TokenType {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
+    Integer(i64),
     Number(f64),
     String(String),
     Boolean(bool),
@@ -68,6 +107,7 @@ pub enum Literal {
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Literal::Integer(value) => write!(f, "{}", value),
             Literal::Number(value) => write!(f, "{}", value),
             Literal::String(value) => write!(f, "{}", value),
             Literal::Boolean(value) => write!(f, "{}", value),
@@ -120,12 +160,28 @@ impl fmt::Display for Coordinate {
     }
 }
 
+// where the resolver found a local variable, "depth" scopes out from the one
+// it's used in and at "index" in that scope.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    pub depth: u32,
+    pub index: u32,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: Option<String>,
     pub literal: Literal,
     pub coordinate: Coordinate,
+    // the text of the "///" doc comments right before this token, if any.
+    pub doc: Option<String>,
+    // identifiers are interned as they're scanned.
+    pub symbol: Option<Symbol>,
+    // set by the resolver on names of local variables, where they're declared
+    // or used. Names left without a slot live at the top level of a script
+    // or module and are looked up by symbol.
+    pub slot: Option<Slot>,
 }
 
 impl fmt::Display for Token {
@@ -141,11 +197,18 @@ impl Token {
         literal: Literal,
         coordinate: Coordinate,
     ) -> Token {
+        let symbol = match (token_type, &lexeme) {
+            (TokenType::Identifier, Some(lexeme)) => Some(Symbol::intern(lexeme)),
+            _ => None,
+        };
         Token {
             token_type,
             lexeme,
             literal,
             coordinate,
+            doc: None,
+            symbol,
+            slot: None,
         }
     }
 
@@ -164,10 +227,15 @@ impl Token {
         }
     }
 
+    pub fn symbol(&self) -> Symbol {
+        self.symbol
+            .unwrap_or_else(|| Symbol::intern(&self.lexeme_or_empty()))
+    }
+
     pub fn lexeme_or_empty(&self) -> String {
         self.lexeme
             .as_ref()
             .map(|s| s.to_string())
-            .unwrap_or(String::new())
+            .unwrap_or_default()
     }
 }
@@ -1,2 +1,7 @@
+#![allow(clippy::result_large_err)]
+
+pub mod cli;
 pub mod interpreter;
 pub mod language;
+#[cfg(test)]
+pub mod testing;
@@ -1,60 +1,57 @@
-//use lox::cli::{Cli, CliError};
+use rlox::cli::{Cli, CliError};
+use rlox::interpreter::errors::RuntimeError;
 use rlox::interpreter::visitor::LoxVisitor;
+use rlox::language::checker::Checker;
 use rlox::language::errors::ParseError;
 use rlox::language::parser::Parser;
 use rlox::language::scanner::Scanner;
+use std::path::Path;
+use std::process;
 
 fn main() {
-    let input = r#"
-        fun fib(n) {
-            if (n <= 1) return n;
-            var a = 0;
-            var b = 1;
-            while (n > 1) {
-                var c = a + b;
-                a = b;
-                b = c;
-                n = n - 1;
-            }
-            return b;
+    let cli = match Cli::new() {
+        Ok(cli) => cli,
+        Err(e) => {
+            handle_cli_error(e);
+            process::exit(64);
         }
+    };
 
-        var start = clock();
-        print fib(1000000);
-        print clock() - start;
-    "#;
-    let tokens = Scanner::new(input).scan_tokens();
+    let tokens = Scanner::new(&cli.source).scan_tokens();
 
     if tokens.is_err() {
         println!("Error scanning tokens: {:?}", tokens.err().unwrap());
-        return;
+        process::exit(65);
     }
 
     let ast = Parser::new(tokens.unwrap()).parse();
 
     if ast.is_err() {
         print_ast_errors(ast.err().unwrap());
-        return;
+        process::exit(65);
     }
 
-    let result = LoxVisitor::new().interpret(&ast.unwrap());
-
-    if result.is_err() {
-        // println!("Error interpreting ast");
-        println!("{}", result.err().unwrap());
+    let ast = ast.unwrap();
+    if cli.args.check {
+        if let Err(errors) = Checker::new().check(&ast) {
+            print_ast_errors(errors);
+            process::exit(65);
+        }
         return;
     }
 
-    //println!("{:?}", result.unwrap());
-    // let cli = Cli::new();
-
-    // if cli.is_err() {
-    //     handle_cli_error(cli.err().unwrap());
-    //     return;
-    // }
+    let mut interpreter = LoxVisitor::new();
+    interpreter.set_script_path(Path::new(&cli.args.source));
+    interpreter.set_script_args(cli.args.script_args);
 
-    // let cli = cli.unwrap();
-    // println!("{}", cli.source);
+    match interpreter.interpret(&ast) {
+        Ok(()) => {}
+        Err(RuntimeError::Exit(code)) => process::exit(code),
+        Err(e) => {
+            println!("{}", e);
+            process::exit(70);
+        }
+    }
 }
 
 fn print_ast_errors(errors: Vec<ParseError>) {
@@ -63,22 +60,19 @@ fn print_ast_errors(errors: Vec<ParseError>) {
     }
 }
 
-// fn print_usage() {
-//     println!("Usage: lox [script]");
-// }
+fn print_usage() {
+    println!("Usage: rlox [--] [script] [args...]");
+    println!("       rlox --check [script]");
+}
 
-// fn handle_cli_error(cli_err: CliError) {
-//     match cli_err {
-//         CliError::InvalidArgumentsLength => {
-//             println!("Invalid number of arguments");
-//             print_usage();
-//         }
-//         CliError::NoArguments => {
-//             println!("No arguments provided");
-//             print_usage();
-//         }
-//         CliError::FileReadError { path, error } => {
-//             println!("Error reading file \"{}\": {}", path, error);
-//         }
-//     }
-// }
+fn handle_cli_error(cli_err: CliError) {
+    match cli_err {
+        CliError::NoArguments => {
+            println!("No arguments provided");
+            print_usage();
+        }
+        CliError::FileReadError { path, error } => {
+            println!("Error reading file \"{}\": {}", path, error);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+// helpers shared by the tests, the scripts given to them are expected to scan.
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::primitive::LoxObject;
+use crate::interpreter::visitor::LoxVisitor;
+use crate::language::ast::Stmt;
+use crate::language::errors::ParseError;
+use crate::language::parser::Parser;
+use crate::language::scanner::Scanner;
+
+// scans and parses, which resolves the script as well.
+pub fn parse(input: &str) -> Result<Vec<Stmt>, Vec<ParseError>> {
+    let tokens = Scanner::new(input).scan_tokens().unwrap();
+    Parser::new(tokens).parse()
+}
+
+// runs a script on a fresh interpreter, handing it back to inspect afterwards.
+pub fn run(input: &str) -> (LoxVisitor, Result<(), RuntimeError>) {
+    run_with(LoxVisitor::new(), input)
+}
+
+pub fn s(v: &str) -> LoxObject {
+    LoxObject::String(v.to_string())
+}
+
+pub fn n(v: f64) -> LoxObject {
+    LoxObject::Number(v)
+}
+
+// calls a native directly, on a fresh interpreter.
+pub fn call(
+    f: fn(&mut LoxVisitor, &[LoxObject]) -> Result<LoxObject, RuntimeError>,
+    args: &[LoxObject],
+) -> Result<LoxObject, RuntimeError> {
+    f(&mut LoxVisitor::new(), args)
+}
+
+pub fn run_with(
+    mut interpreter: LoxVisitor,
+    input: &str,
+) -> (LoxVisitor, Result<(), RuntimeError>) {
+    let ast = parse(input).unwrap();
+    let result = interpreter.interpret(&ast);
+    (interpreter, result)
+}